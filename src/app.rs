@@ -1,144 +1,494 @@
-use minifb::Key;
-use minifb::{Window, WindowOptions, ScaleMode, Scale};
-
 use crate::Chip9;
 use crate::errors::Chip9Error;
-use crate::chip9::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
-use crate::chip9::Keyboard;
-use std::collections::HashMap;
+use crate::frontend::{Command, Frame, Frontend};
+use crate::palette::{Palette, Theme};
+use crate::persistence::{Persistence, Phosphor};
+use crate::recording::Recorder;
+use crate::rewind::{Rewind, DEFAULT_REWIND_SECONDS};
+use crate::screenshot::DEFAULT_SCREENSHOT_SCALE;
+use crate::chip9::{Display, DEFAULT_IPF, FRAME_RATE};
+use crate::chip9::cpu::OpCode;
+use std::fs::File;
+use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 use std::thread;
 
-const WINDOW_NAME: &str = "Chip9";
-const CPU_FREQ: f64 = 1.0 / 700.0;
-
+/// Runs a machine on a frontend: schedules frames, handles the hotkeys and
+/// turns the display into colored frames
 pub struct Emulator {
-    window: Option<Window>,
-    buffer: Vec<u32>,
-    colors: Colors,
-    bindings: Bindings
+    palette: Palette,
+    theme: Theme, // last theme picked, the palette may have been customized since
+    phosphor: Phosphor,
+    ipf: u32,
+    state_path: Option<PathBuf>,
+    capture_path: Option<PathBuf>,
+    capture_scale: usize,
+    recorder: Option<Recorder>,
+    rewind: Rewind,
+    speed: Speed,
+    paused: bool,
+    pending_frames: f64, // emulated frames owed at slow speeds
+    keys: u16, // CHIP-8 keys held, from the last poll
+    presented: Option<u64>, // display generation on screen, None when the next frame must be presented
+}
+
+impl Default for Emulator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
-// todo: refactor from the ground up, maybe pixels + winit?
 impl Emulator {
     pub fn new() -> Self {
-        let buffer: Vec<u32> = vec![0; 64 * 32];
-        let colors = Colors {
-            filled: Color::from((0xFF, 0xFF, 0xFF)),
-            empty: Color::from((0, 0, 0))
-        };
-
-        Self { window: None, buffer, colors, bindings: Bindings::default() }
+        Self {
+            palette: Palette::default(),
+            theme: Theme::default(),
+            phosphor: Phosphor::new(Persistence::default()),
+            ipf: DEFAULT_IPF,
+            state_path: None,
+            capture_path: None,
+            capture_scale: DEFAULT_SCREENSHOT_SCALE,
+            recorder: None,
+            rewind: Rewind::new(DEFAULT_REWIND_SECONDS),
+            speed: Speed::Normal,
+            paused: false,
+            pending_frames: 0.0,
+            keys: 0,
+            presented: None,
+        }
     }
 
-    pub fn run(&mut self, mut chip9: Chip9) -> Result<(), Chip9Error> {
-        let window = Window::new(
-            WINDOW_NAME,
-            DISPLAY_WIDTH,
-            DISPLAY_HEIGHT,
-            WindowOptions {
-                resize: true,
-                scale: Scale::X16,
-                scale_mode: ScaleMode::AspectRatioStretch,
-                ..WindowOptions::default()
-            },
-        )
-        .map_err(Chip9Error::WindowCreationError)?;
+    /// Runs until the program exits or the frontend asks to quit
+    pub fn run(&mut self, chip9: &mut Chip9, frontend: &mut dyn Frontend) -> Result<(), Chip9Error> {
+        let frame = Duration::from_secs_f64(1.0 / FRAME_RATE as f64);
+        let mut next_frame = Instant::now();
+        frontend.set_status(&self.status());
 
-        self.window = Some(window);
+        while !chip9.is_halted() {
+            let input = frontend.poll_input()?;
+            if input.quit {
+                break;
+            }
+            self.keys = input.keys;
 
-        let tick = Duration::from_secs_f64(CPU_FREQ);
-        let mut next = Instant::now() + tick;
+            let (paused, speed) = (self.paused, self.speed);
+            for &command in &input.commands {
+                self.command(command, chip9, frontend)?;
+            }
+            if (paused, speed) != (self.paused, self.speed) {
+                frontend.set_status(&self.status());
+            }
 
-        while self.window.as_ref().unwrap().is_open() {
-            self.update_keyboard(&mut chip9.keyboard);
-            self.render(&chip9.display)?;
-            let now = Instant::now();
-            if now >= next {
-                chip9.tick()?; // todo: add audio
-                next += tick
+            if input.rewinding {
+                self.rewind.step_back(chip9, self.ipf)?;
+            } else if self.paused {
+                // Only stepped by commands
+            } else if !frontend.is_realtime() {
+                self.run_frames(chip9, 1)?;
             } else {
-                thread::sleep(next - now);
+                match self.speed.factor() {
+                    Some(factor) => {
+                        self.pending_frames += factor;
+                        let frames = self.pending_frames as u32;
+                        self.pending_frames -= frames as f64;
+                        self.run_frames(chip9, frames)?;
+                    }
+                    // As many frames as fit before the next one is due
+                    None => while Instant::now() < next_frame + frame && !chip9.is_halted() {
+                        self.run_frames(chip9, 1)?;
+                    },
+                }
+            }
+            frontend.audio().set_pattern(chip9.audio_pattern());
+            frontend.audio().set_playing(chip9.sound_timer() > 0 && !self.paused);
+            if self.presented != Some(chip9.display.generation()) || self.phosphor.is_fading() {
+                frontend.present(&self.frame(&chip9.display))?;
+                self.presented = Some(chip9.display.generation());
+            }
+
+            if frontend.is_realtime() {
+                next_frame += frame;
+                let now = Instant::now();
+                if next_frame > now {
+                    thread::sleep(next_frame - now);
+                } else if now - next_frame > frame {
+                    next_frame = now; // fell behind, don't rush to catch up
+                }
             }
         }
 
+        frontend.audio().set_playing(false);
+        self.recorder.take().map_or(Ok(()), Recorder::finish)
+    }
+
+    fn run_frames(&mut self, chip9: &mut Chip9, frames: u32) -> Result<(), Chip9Error> {
+        for _ in 0..frames {
+            if chip9.is_halted() {
+                break;
+            }
+            chip9.keyboard.set_mask(self.keys);
+            self.rewind.record(chip9)?;
+            chip9.run_frame(self.ipf)?;
+            // One capture per emulated frame keeps the GIF at 60Hz whatever the speed, frame steps aren't gameplay
+            if let Some(recorder) = self.recorder.as_mut().filter(|_| !self.paused) {
+                recorder.capture(&chip9.display, &self.palette)?;
+            }
+        }
         Ok(())
     }
 
-    fn render(&mut self, display: &Display) -> Result<(), Chip9Error> {
-        let grid = display.grid();
-        for j in 0..DISPLAY_HEIGHT {
-            for i in 0..DISPLAY_WIDTH {
-                let color = if grid[i][j] { &self.colors.filled } else { &self.colors.empty };
-                self.buffer[i + j * DISPLAY_WIDTH] = color.value();
+    fn command(&mut self, command: Command, chip9: &mut Chip9, frontend: &mut dyn Frontend) -> Result<(), Chip9Error> {
+        match command {
+            Command::Pause => self.paused = !self.paused,
+            Command::FrameStep if self.paused => self.run_frames(chip9, 1)?,
+            Command::InstructionStep if self.paused => {
+                chip9.keyboard.set_mask(self.keys);
+                let pc = chip9.cpu().program_counter();
+                chip9.tick()?;
+                // A draw waiting for the vertical blank would never get past it one instruction at a time
+                if chip9.cpu().program_counter() == pc && matches!(chip9.cpu().decode_at(pc), Ok(OpCode::Draw(..))) {
+                    chip9.vblank();
+                    chip9.tick()?;
+                }
+                self.rewind.checkpoint(chip9)?;
             }
+            Command::FrameStep | Command::InstructionStep => (),
+            Command::Faster => self.speed = self.speed.faster(),
+            Command::Slower => self.speed = self.speed.slower(),
+            Command::CycleTheme => self.set_theme(self.theme.next()),
+            Command::ToggleMute => frontend.audio().toggle_mute(),
+            Command::SaveState(slot) => self.save_state(chip9, slot),
+            Command::LoadState(slot) => self.load_state(chip9, slot),
+            Command::Screenshot => self.screenshot(chip9),
+            Command::ToggleRecording => self.toggle_recording(),
+        }
+        Ok(())
+    }
+
+    // Shown by the frontend
+    fn status(&self) -> String {
+        match self.paused {
+            true => "paused".to_string(),
+            false => self.speed.label().to_string(),
+        }
+    }
+
+    fn save_state(&self, chip9: &Chip9, slot: usize) {
+        let Some(path) = self.state_file(slot) else {
+            return;
+        };
+        let result = File::create(&path)
+            .map_err(Chip9Error::StateIoError)
+            .and_then(|mut file| chip9.save_state(&mut file));
+        if let Err(e) = result {
+            eprintln!("State slot {} ({}): {e}", slot, path.display());
         }
-        self.window.as_mut().unwrap()
-            .update_with_buffer(&self.buffer, DISPLAY_WIDTH, DISPLAY_HEIGHT)
-            .map_err(Chip9Error::WindowUpdateError)
     }
 
-    fn update_keyboard(&self, keyboard: &mut Keyboard) {
-        let window = self.window.as_ref().unwrap();
-        let pressed_keys: Vec<u8> = window.get_keys()
-            .iter()
-            .filter_map(|key| self.bindings.get_chip9_key(key))
+    fn load_state(&mut self, chip9: &mut Chip9, slot: usize) {
+        let Some(path) = self.state_file(slot) else {
+            return;
+        };
+        let result = File::open(&path)
+            .map_err(Chip9Error::StateIoError)
+            .and_then(|mut file| chip9.load_state(&mut file));
+        match result {
+            Ok(()) => self.rewind.clear(),
+            Err(e) => eprintln!("State slot {} ({}): {e}", slot, path.display()),
+        }
+    }
+
+    fn state_file(&self, slot: usize) -> Option<PathBuf> {
+        self.state_path.as_ref()
+            .map(|path| path.with_extension(format!("state{}", slot)))
+    }
+
+    fn screenshot(&self, chip9: &Chip9) {
+        let Some(path) = self.capture_file("png") else {
+            return;
+        };
+        if let Err(e) = chip9.save_screenshot(&path, &self.palette, self.capture_scale) {
+            eprintln!("Screenshot ({}): {e}", path.display());
+        }
+    }
+
+    fn toggle_recording(&mut self) {
+        let result = match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => match self.capture_file("gif") {
+                Some(path) => self.start_recording(&path),
+                None => Ok(()),
+            },
+        };
+        if let Err(e) = result {
+            eprintln!("{e}");
+        }
+    }
+
+    // First of `name-1.ext`, `name-2.ext`... that doesn't exist yet
+    fn capture_file(&self, extension: &str) -> Option<PathBuf> {
+        let path = self.capture_path.as_ref()?;
+        let name = path.file_stem()?.to_string_lossy();
+        (1..).map(|n| path.with_file_name(format!("{}-{}.{}", name, n, extension)))
+            .find(|file| !file.exists())
+    }
+
+    fn frame(&mut self, display: &Display) -> Frame {
+        self.phosphor.update(display);
+        let (width, height) = (display.width(), display.height());
+        let pixels = (0..height)
+            .flat_map(|y| (0..width).map(move |x| (x, y)))
+            .map(|(x, y)| self.phosphor.color(x, y, &self.palette))
             .collect();
-        keyboard.set_pressed(&pressed_keys);
+        Frame { width, height, pixels, background: self.palette.get(0) }
     }
-}
 
-const DEFAULT_BINDINGS: [(Key, u8); 16] = [
-    (Key::Key1, 0x1),
-    (Key::Key2, 0x2),
-    (Key::Key3, 0x3),
-    (Key::Key4, 0xC),
-    (Key::Q,    0x4),
-    (Key::W,    0x5),
-    (Key::E,    0x6),
-    (Key::R,    0xD),
-    (Key::A,    0x7),
-    (Key::S,    0x8),
-    (Key::D,    0x9),
-    (Key::F,    0xE),
-    (Key::Z,    0xA),
-    (Key::X,    0x0),
-    (Key::C,    0xB),
-    (Key::V,    0xF),
-];
-
-// todo: refactor this from the ground up
-struct Bindings(HashMap<Key, u8>);
-
-impl Default for Bindings {
-    fn default() -> Self {
-        Self(HashMap::from(DEFAULT_BINDINGS))
+    /// Save state slots are stored next to `path`, with its extension replaced by `state0` to `state9`
+    pub fn set_state_path(&mut self, path: &Path) {
+        self.state_path = Some(path.to_path_buf());
     }
-}
 
-impl Bindings {
-    pub fn get_chip9_key(&self, key: &Key) -> Option<u8> {
-        self.0.get(key).copied()
+    /// Screenshots and recordings are saved next to `path`, numbered from 1: `game.ch8` uses
+    /// `game-1.png`, `game-2.png`... and `game-1.gif`, `game-2.gif`...
+    pub fn set_capture_path(&mut self, path: &Path) {
+        self.capture_path = Some(path.to_path_buf());
+    }
+
+    /// Pixel size of screenshots and recordings, in image pixels per CHIP-8 pixel
+    pub fn set_capture_scale(&mut self, scale: usize) {
+        self.capture_scale = scale;
+    }
+
+    /// Records every frame from now on into a GIF at `path`, until F11 is pressed or the run ends
+    pub fn start_recording(&mut self, path: &Path) -> Result<(), Chip9Error> {
+        self.recorder = Some(Recorder::create(path, &self.palette, self.capture_scale)?);
+        Ok(())
     }
-}
 
-struct Colors {
-    filled: Color,
-    empty: Color
+    /// Seconds of gameplay that can be rewound by holding Backspace, 0 disables rewinding
+    pub fn set_rewind_seconds(&mut self, seconds: u32) {
+        self.rewind = Rewind::new(seconds);
+    }
+
+    /// Instructions executed per 60Hz frame, sets the emulation speed
+    pub fn set_instructions_per_frame(&mut self, ipf: u32) {
+        self.ipf = ipf;
+    }
+
+    /// Sets the colors of the 4 pixel values of the 2-plane display
+    pub fn set_palette(&mut self, palette: Palette) {
+        self.palette = palette;
+        self.presented = None;
+    }
+
+    /// Lets pixels fade out over the next frames instead of turning off at once, hiding sprite flicker
+    pub fn set_persistence(&mut self, persistence: Persistence) {
+        self.phosphor = Phosphor::new(persistence);
+    }
+
+    /// Switches to a built-in palette, T cycles to the next one
+    pub fn set_theme(&mut self, theme: Theme) {
+        self.theme = theme;
+        self.set_palette(theme.palette());
+    }
 }
 
-pub struct Color {
-    value: u32,
+// Emulation speed relative to 60 frames per second
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Speed {
+    Half,
+    Normal,
+    Double,
+    Quadruple,
+    Unbounded,
 }
 
-impl From<(u8, u8, u8)> for Color {
-    fn from((r, g, b): (u8, u8, u8)) -> Self {
-        Self { value: ((r as u32) << 16) | ((g as u32) << 8) | b as u32 }
+impl Speed {
+    const ALL: [Speed; 5] = [Speed::Half, Speed::Normal, Speed::Double, Speed::Quadruple, Speed::Unbounded];
+
+    // Emulated frames per displayed frame, None runs as fast as possible
+    fn factor(&self) -> Option<f64> {
+        match self {
+            Speed::Half => Some(0.5),
+            Speed::Normal => Some(1.0),
+            Speed::Double => Some(2.0),
+            Speed::Quadruple => Some(4.0),
+            Speed::Unbounded => None,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            Speed::Half => "0.5x",
+            Speed::Normal => "1x",
+            Speed::Double => "2x",
+            Speed::Quadruple => "4x",
+            Speed::Unbounded => "unbounded",
+        }
+    }
+
+    fn faster(self) -> Self {
+        let i = Self::ALL.iter().position(|&speed| speed == self).unwrap();
+        Self::ALL[(i + 1).min(Self::ALL.len() - 1)]
+    }
+
+    fn slower(self) -> Self {
+        let i = Self::ALL.iter().position(|&speed| speed == self).unwrap();
+        Self::ALL[i.saturating_sub(1)]
     }
 }
 
-impl Color {
-    fn value(&self) -> u32 {
-        self.value
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::audio::AudioPattern;
+    use crate::chip9::Quirks;
+    use crate::frontend::{Input, MockFrontend};
+
+    fn machine(rom: &[u8]) -> Chip9 {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.set_seed(0);
+        chip9.load_rom(rom).unwrap();
+        chip9
+    }
+
+    fn commands(commands: &[Command]) -> Input {
+        Input { commands: commands.to_vec(), ..Input::default() }
+    }
+
+    #[test]
+    fn quit_stops_before_running() {
+        let mut chip9 = machine(&[0x70, 0x01, 0x12, 0x00]); // V0 += 1, loop
+        let mut frontend = MockFrontend::new([Input { quit: true, ..Input::default() }]);
+
+        Emulator::new().run(&mut chip9, &mut frontend).unwrap();
+
+        assert_eq!(chip9.cpu().registers()[0], 0);
+        assert!(frontend.frames.is_empty());
+        assert_eq!(frontend.audio.playing, [false]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn paused_machine_only_runs_frame_steps() {
+        let mut chip9 = machine(&[0x70, 0x01, 0x12, 0x00]); // V0 += 1, loop
+        let mut frontend = MockFrontend::new([
+            commands(&[Command::Pause]),
+            Input::default(),
+            commands(&[Command::FrameStep]),
+            Input::default(),
+        ]);
+
+        let mut emulator = Emulator::new();
+        emulator.set_instructions_per_frame(2);
+        emulator.run(&mut chip9, &mut frontend).unwrap();
+
+        assert_eq!(chip9.cpu().registers()[0], 1);
+        assert_eq!(frontend.statuses, ["1x", "paused"]);
+    }
+
+    #[test]
+    fn status_shows_the_speed() {
+        let mut chip9 = machine(&[0x12, 0x00]); // loop
+        let mut frontend = MockFrontend::new([
+            commands(&[Command::Faster]),
+            commands(&[Command::Pause]),
+            commands(&[Command::Pause, Command::Slower]),
+        ]);
+
+        Emulator::new().run(&mut chip9, &mut frontend).unwrap();
+
+        assert_eq!(frontend.statuses, ["1x", "2x", "paused", "1x"]);
+    }
+
+    #[test]
+    fn instruction_step_gets_past_display_wait() {
+        let mut chip9 = Chip9::new(Quirks::COSMAC_VIP);
+        chip9.load_rom(&[0xD0, 0x05, 0x12, 0x02]).unwrap(); // draw, loop
+        let mut frontend = MockFrontend::new([commands(&[Command::Pause, Command::InstructionStep])]);
+
+        Emulator::new().run(&mut chip9, &mut frontend).unwrap();
+
+        assert_eq!(chip9.cpu().program_counter(), 0x202);
+        assert_eq!(chip9.display.pixel(0, 0), 1);
+    }
+
+    #[test]
+    fn beeper_plays_while_sound_timer_runs() {
+        let mut chip9 = machine(&[0x60, 0x03, 0xF0, 0x18, 0x12, 0x04]); // ST := 3, loop
+        let mut frontend = MockFrontend::new([Input::default(), Input::default(), Input::default(), Input::default()]);
+
+        Emulator::new().run(&mut chip9, &mut frontend).unwrap();
+
+        // ST is 2, 1, 0, 0 after each frame, then the beeper is stopped on exit
+        assert_eq!(frontend.audio.playing, [true, true, false, false, false]);
+    }
+
+    #[test]
+    fn audio_pattern_reaches_the_beeper() {
+        let mut rom = vec![0xA2, 0x06, 0xF0, 0x02, 0x12, 0x04]; // I := pattern, audio, loop
+        rom.extend(0xF0..=0xFF);
+        let mut chip9 = machine(&rom);
+        let mut frontend = MockFrontend::new([Input::default()]);
+
+        Emulator::new().run(&mut chip9, &mut frontend).unwrap();
+
+        let samples = std::array::from_fn(|i| 0xF0 + i as u8);
+        assert_eq!(frontend.audio.pattern, Some(AudioPattern { samples, rate: 4000.0 }));
+    }
+
+    #[test]
+    fn unchanged_display_is_not_presented_again() {
+        let mut chip9 = machine(&[0x12, 0x00]); // loop
+        let mut frontend = MockFrontend::new([Input::default(), Input::default(), Input::default()]);
+
+        Emulator::new().run(&mut chip9, &mut frontend).unwrap();
+
+        assert_eq!(frontend.frames.len(), 1);
+    }
+
+    #[test]
+    fn changed_display_is_presented() {
+        // Draws the font sprite at I = 0 on the second frame, then loops
+        let mut chip9 = machine(&[0x12, 0x04, 0x00, 0x00, 0xD0, 0x05, 0x12, 0x06]);
+        let mut frontend = MockFrontend::new([Input::default(), Input::default(), Input::default()]);
+
+        let mut emulator = Emulator::new();
+        emulator.set_instructions_per_frame(1);
+        emulator.run(&mut chip9, &mut frontend).unwrap();
+
+        assert_eq!(frontend.frames.len(), 2);
+        assert_ne!(frontend.frames[0], frontend.frames[1]);
+    }
+
+    #[test]
+    fn recording_captures_emulated_frames_only() {
+        let path = std::env::temp_dir().join(format!("chip9-recording-{}.gif", std::process::id()));
+        let mut chip9 = machine(&[0xD0, 0x05, 0x12, 0x00]); // redraw the same sprite every frame, blinking it
+        let mut frontend = MockFrontend::new([
+            Input::default(),
+            Input::default(),
+            Input::default(),
+            Input::default(),
+            commands(&[Command::Pause]),
+            Input::default(),
+            Input::default(),
+            Input::default(),
+        ]);
+
+        let mut emulator = Emulator::new();
+        emulator.set_instructions_per_frame(2);
+        emulator.start_recording(&path).unwrap();
+        emulator.run(&mut chip9, &mut frontend).unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        let mut delay = 0;
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            delay += frame.delay;
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(delay, 7); // 4 frames at 60Hz, in 1/100s
+    }
+}
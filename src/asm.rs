@@ -0,0 +1,857 @@
+mod calc;
+mod lexer;
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::io::{self, Write};
+
+use crate::chip9::cpu::PROGRAM_START;
+use crate::errors::Chip9Error;
+use calc::{parse_number, Calc};
+use lexer::{tokenize, Token};
+
+const ENTRY_POINT: &str = "main";
+const MAX_EXPANSIONS: usize = 100_000; // guards against recursive macros
+const JUMP_PLACEHOLDER: u16 = 0x1000;
+
+/// Assembled ROM with the addresses of its labels
+pub struct Program {
+    pub rom: Vec<u8>,
+    pub symbols: BTreeMap<String, u16>,
+}
+
+impl Program {
+    /// Writes one `0xADDR name` line per label, sorted by address
+    pub fn write_symbols(&self, mut output: impl Write) -> io::Result<()> {
+        let mut symbols: Vec<(&String, &u16)> = self.symbols.iter().collect();
+        symbols.sort_by_key(|&(name, addr)| (*addr, name.clone()));
+        for (name, addr) in symbols {
+            writeln!(output, "0x{:04X} {}", addr, name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Assembles Octo source into a ROM loaded at `PROGRAM_START`
+pub fn assemble(source: &str) -> Result<Program, Chip9Error> {
+    Assembler::new(source).assemble()
+}
+
+enum FixupKind {
+    Addr12, // low 12 bits of an instruction
+    Addr16, // whole word, XO-CHIP long address
+}
+
+// Reference to a label that wasn't defined yet
+struct Fixup {
+    addr: u16,
+    name: String,
+    line: usize,
+    kind: FixupKind,
+}
+
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+enum Operand {
+    Reg(u8),
+    Value(i64),
+}
+
+struct Loop {
+    start: u16,
+    exits: Vec<u16>, // `while` jumps patched by `again`
+}
+
+struct Assembler {
+    tokens: VecDeque<Token>,
+    line: usize,
+    rom: Vec<u8>,
+    here: u32, // reaches 0x10000 once the last byte of memory was written
+    started: bool, // entry jump to `main` was decided
+    labels: HashMap<String, u16>,
+    consts: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    branches: Vec<u16>, // `if ... begin` jumps patched by `else`/`end`
+    loops: Vec<Loop>,
+    next_label: Option<String>,
+    expansions: usize,
+}
+
+impl Assembler {
+    fn new(source: &str) -> Self {
+        Self {
+            tokens: tokenize(source).into(),
+            line: 0,
+            rom: Vec::new(),
+            here: PROGRAM_START as u32,
+            started: false,
+            labels: HashMap::new(),
+            consts: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            fixups: Vec::new(),
+            branches: Vec::new(),
+            loops: Vec::new(),
+            next_label: None,
+            expansions: 0,
+        }
+    }
+
+    fn error(&self, message: impl Into<String>) -> Chip9Error {
+        Chip9Error::AssemblyError(self.line, message.into())
+    }
+
+    fn assemble(mut self) -> Result<Program, Chip9Error> {
+        while let Some(token) = self.next() {
+            self.statement(&token)?;
+        }
+
+        if !self.branches.is_empty() {
+            return Err(self.error("'begin' without matching 'end'"));
+        }
+        if !self.loops.is_empty() {
+            return Err(self.error("'loop' without matching 'again'"));
+        }
+        if let Some(name) = &self.next_label {
+            return Err(self.error(format!("':next {}' is not followed by an instruction", name)));
+        }
+
+        for fixup in std::mem::take(&mut self.fixups) {
+            self.line = fixup.line;
+            let addr = *self.labels.get(&fixup.name)
+                .ok_or_else(|| self.error(format!("undefined label '{}'", fixup.name)))?;
+            match fixup.kind {
+                FixupKind::Addr12 => {
+                    let code = self.word(fixup.addr);
+                    self.patch(fixup.addr, (code & 0xF000) | self.addr12(addr)?);
+                }
+                FixupKind::Addr16 => self.patch(fixup.addr, addr),
+            }
+        }
+
+        Ok(Program { rom: self.rom, symbols: self.labels.into_iter().collect() })
+    }
+
+    // Token stream
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.pop_front()?;
+        self.line = token.line;
+        Some(token)
+    }
+
+    fn expect(&mut self) -> Result<String, Chip9Error> {
+        self.next()
+            .map(|token| token.text)
+            .ok_or_else(|| self.error("unexpected end of file"))
+    }
+
+    fn expect_text(&mut self, text: &str) -> Result<(), Chip9Error> {
+        let token = self.expect()?;
+        if token != text {
+            return Err(self.error(format!("expected '{}', found '{}'", text, token)));
+        }
+        Ok(())
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.front().is_some_and(|token| token.text == text)
+    }
+
+    // Tokens up to the `}` matching an already consumed `{`
+    fn block(&mut self) -> Result<Vec<Token>, Chip9Error> {
+        let mut depth = 1;
+        let mut body = Vec::new();
+        loop {
+            let token = self.next().ok_or_else(|| self.error("'{' without matching '}'"))?;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => (),
+            }
+            if depth == 0 {
+                return Ok(body);
+            }
+            body.push(token);
+        }
+    }
+
+    // Output
+
+    // The ROM starts with a jump to `main`, unless `main` is the very first label
+    fn start(&mut self, label: Option<&str>) -> Result<(), Chip9Error> {
+        if self.started {
+            return Ok(());
+        }
+        self.started = true;
+        if label != Some(ENTRY_POINT) {
+            self.emit_address_op(JUMP_PLACEHOLDER, ENTRY_POINT)?;
+        }
+        Ok(())
+    }
+
+    // Address of the next byte written, an error once memory is full
+    fn here(&self) -> Result<u16, Chip9Error> {
+        u16::try_from(self.here).map_err(|_| self.error("program doesn't fit in memory"))
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), Chip9Error> {
+        self.start(None)?;
+        if let Some(name) = &self.next_label {
+            return Err(self.error(format!("':next {}' must be followed by an instruction, not data", name)));
+        }
+        let offset = (self.here()? - PROGRAM_START) as usize;
+        if offset >= self.rom.len() {
+            self.rom.resize(offset + 1, 0);
+        }
+        self.rom[offset] = byte;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, code: u16) -> Result<(), Chip9Error> {
+        self.start(None)?;
+        if let Some(name) = self.next_label.take() {
+            let addr = self.here()?.checked_add(1).ok_or_else(|| self.error("program doesn't fit in memory"))?;
+            self.define_label(name, addr)?;
+        }
+        let [high, low] = code.to_be_bytes();
+        self.emit_byte(high)?;
+        self.emit_byte(low)
+    }
+
+    fn word(&self, addr: u16) -> u16 {
+        let offset = (addr - PROGRAM_START) as usize;
+        u16::from_be_bytes([self.rom[offset], self.rom[offset + 1]])
+    }
+
+    fn patch(&mut self, addr: u16, code: u16) {
+        let offset = (addr - PROGRAM_START) as usize;
+        self.rom[offset..offset + 2].copy_from_slice(&code.to_be_bytes());
+    }
+
+    fn patch_jump(&mut self, addr: u16, target: u16) -> Result<(), Chip9Error> {
+        let target = self.addr12(target)?;
+        self.patch(addr, JUMP_PLACEHOLDER | target);
+        Ok(())
+    }
+
+    fn addr12(&self, addr: u16) -> Result<u16, Chip9Error> {
+        if addr > 0xFFF {
+            return Err(self.error(format!("address 0x{:X} is out of reach of 12-bit instructions", addr)));
+        }
+        Ok(addr)
+    }
+
+    // Emits `code` with its low 12 bits taken from `target`, resolved later if it's a forward label
+    fn emit_address_op(&mut self, code: u16, target: &str) -> Result<(), Chip9Error> {
+        match self.value(target) {
+            Some(addr) => {
+                let addr = self.addr12(self.to_u16(addr)?)?;
+                self.emit(code | addr)
+            }
+            None => {
+                self.emit(code)?;
+                self.add_fixup(self.here as u16 - 2, target, FixupKind::Addr12)
+            }
+        }
+    }
+
+    fn add_fixup(&mut self, addr: u16, name: &str, kind: FixupKind) -> Result<(), Chip9Error> {
+        if !is_identifier(name) {
+            return Err(self.error(format!("expected an address, found '{}'", name)));
+        }
+        self.fixups.push(Fixup { addr, name: name.to_string(), line: self.line, kind });
+        Ok(())
+    }
+
+    fn define_label(&mut self, name: String, addr: u16) -> Result<(), Chip9Error> {
+        if self.labels.contains_key(&name) || self.consts.contains_key(&name) {
+            return Err(self.error(format!("'{}' is already defined", name)));
+        }
+        self.labels.insert(name, addr);
+        Ok(())
+    }
+
+    // Operands
+
+    fn value(&self, text: &str) -> Option<i64> {
+        parse_number(text)
+            .or_else(|| self.consts.get(text).map(|&value| value as i64))
+            .or_else(|| self.labels.get(text).map(|&addr| addr as i64))
+    }
+
+    fn to_u16(&self, value: i64) -> Result<u16, Chip9Error> {
+        u16::try_from(value).map_err(|_| self.error(format!("{} is not a valid address", value)))
+    }
+
+    fn register(&self, text: &str) -> Option<u8> {
+        if let Some(&reg) = self.aliases.get(text) {
+            return Some(reg);
+        }
+        let digit = text.strip_prefix('v').or_else(|| text.strip_prefix('V'))?;
+        match digit.len() {
+            1 => u8::from_str_radix(digit, 16).ok(),
+            _ => None,
+        }
+    }
+
+    fn expect_register(&mut self) -> Result<u16, Chip9Error> {
+        let token = self.expect()?;
+        self.register(&token)
+            .map(|reg| reg as u16)
+            .ok_or_else(|| self.error(format!("expected a register, found '{}'", token)))
+    }
+
+    fn expect_value(&mut self) -> Result<i64, Chip9Error> {
+        let token = self.expect()?;
+        self.value(&token).ok_or_else(|| self.error(format!("expected a number, found '{}'", token)))
+    }
+
+    fn expect_byte(&mut self) -> Result<u16, Chip9Error> {
+        let value = self.expect_value()?;
+        if !(-128..=255).contains(&value) {
+            return Err(self.error(format!("{} doesn't fit in a byte", value)));
+        }
+        Ok(value as u8 as u16)
+    }
+
+    fn expect_nibble(&mut self) -> Result<u16, Chip9Error> {
+        let value = self.expect_value()?;
+        if !(0..=15).contains(&value) {
+            return Err(self.error(format!("{} doesn't fit in a nibble", value)));
+        }
+        Ok(value as u16)
+    }
+
+    fn expect_operand(&mut self) -> Result<Operand, Chip9Error> {
+        let token = self.expect()?;
+        if let Some(reg) = self.register(&token) {
+            return Ok(Operand::Reg(reg));
+        }
+        self.tokens.push_front(Token { text: token, line: self.line });
+        let value = self.expect_byte()?;
+        Ok(Operand::Value(value as i64))
+    }
+
+    // Statements
+
+    fn statement(&mut self, token: &Token) -> Result<(), Chip9Error> {
+        let text = token.text.as_str();
+        match text {
+            ":" => {
+                let name = self.expect()?;
+                self.start(Some(&name))?;
+                self.define_label(name, self.here()?)
+            }
+            ":const" => {
+                let name = self.expect()?;
+                let value = self.expect_value()?;
+                self.define_const(name, value as f64)
+            }
+            ":alias" => {
+                let name = self.expect()?;
+                let reg = self.expect_register()?;
+                self.aliases.insert(name, reg as u8);
+                Ok(())
+            }
+            ":macro" => self.define_macro(),
+            ":calc" => {
+                let name = self.expect()?;
+                self.expect_text("{")?;
+                let value = self.calc()?;
+                self.define_const(name, value)
+            }
+            ":byte" => {
+                let value = if self.peek_is("{") {
+                    self.expect()?;
+                    self.calc()? as i64
+                } else {
+                    self.expect_value()?
+                };
+                self.emit_byte(value as u8)
+            }
+            ":next" => {
+                let name = self.expect()?;
+                self.start(None)?;
+                self.next_label = Some(name);
+                Ok(())
+            }
+            ":org" => {
+                let addr = self.expect_value()?;
+                let addr = self.to_u16(addr)?;
+                if addr < PROGRAM_START {
+                    return Err(self.error(format!("can't place code below 0x{:03X}", PROGRAM_START)));
+                }
+                self.here = addr as u32;
+                Ok(())
+            }
+            ":call" => {
+                let target = self.expect()?;
+                self.emit_address_op(0x2000, &target)
+            }
+            ":breakpoint" => self.expect().map(|_| ()),
+            ":monitor" => self.expect().and_then(|_| self.expect()).map(|_| ()),
+            "return" | ";" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "hires" => self.emit(0x00FF),
+            "lores" => self.emit(0x00FE),
+            "exit" => self.emit(0x00FD),
+            "scroll-down" => {
+                let n = self.expect_nibble()?;
+                self.emit(0x00C0 | n)
+            }
+            "scroll-up" => {
+                let n = self.expect_nibble()?;
+                self.emit(0x00D0 | n)
+            }
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-left" => self.emit(0x00FC),
+            "audio" => self.emit(0xF002),
+            "plane" => {
+                let n = self.expect_nibble()?;
+                self.emit(0xF001 | n << 8)
+            }
+            "jump" => {
+                let target = self.expect()?;
+                self.emit_address_op(0x1000, &target)
+            }
+            "jump0" => {
+                let target = self.expect()?;
+                self.emit_address_op(0xB000, &target)
+            }
+            "native" => {
+                let target = self.expect()?;
+                self.emit_address_op(0x0000, &target)
+            }
+            "bcd" => self.register_op(0xF033),
+            "saveflags" => self.register_op(0xF075),
+            "loadflags" => self.register_op(0xF085),
+            "save" => self.transfer(0xF055, 0x5002),
+            "load" => self.transfer(0xF065, 0x5003),
+            "sprite" => {
+                let x = self.expect_register()?;
+                let y = self.expect_register()?;
+                let n = self.expect_nibble()?;
+                self.emit(0xD000 | x << 8 | y << 4 | n)
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect_text(":=")?;
+                let x = self.expect_register()?;
+                let code = match text {
+                    "delay" => 0xF015,
+                    "buzzer" => 0xF018,
+                    _ => 0xF03A,
+                };
+                self.emit(code | x << 8)
+            }
+            "i" => self.index_statement(),
+            "if" => self.if_statement(),
+            "else" => {
+                let jump = self.branches.pop().ok_or_else(|| self.error("'else' without 'begin'"))?;
+                let skip = self.here()?;
+                self.emit(JUMP_PLACEHOLDER)?;
+                self.patch_jump(jump, self.here()?)?;
+                self.branches.push(skip);
+                Ok(())
+            }
+            "end" => {
+                let jump = self.branches.pop().ok_or_else(|| self.error("'end' without 'begin'"))?;
+                self.patch_jump(jump, self.here()?)
+            }
+            "loop" => {
+                self.start(None)?;
+                self.loops.push(Loop { start: self.here()?, exits: Vec::new() });
+                Ok(())
+            }
+            "while" => {
+                if self.loops.is_empty() {
+                    return Err(self.error("'while' outside of a loop"));
+                }
+                let skip = self.condition()?;
+                self.emit(skip)?;
+                let exit = self.here()?;
+                self.emit(JUMP_PLACEHOLDER)?;
+                self.loops.last_mut().unwrap().exits.push(exit);
+                Ok(())
+            }
+            "again" => {
+                let lp = self.loops.pop().ok_or_else(|| self.error("'again' without 'loop'"))?;
+                let start = self.addr12(lp.start)?;
+                self.emit(JUMP_PLACEHOLDER | start)?;
+                for exit in lp.exits {
+                    self.patch_jump(exit, self.here()?)?;
+                }
+                Ok(())
+            }
+            _ if self.register(text).is_some() => self.register_statement(text),
+            _ if parse_number(text).is_some() => {
+                let value = parse_number(text).unwrap();
+                if !(-128..=255).contains(&value) {
+                    return Err(self.error(format!("{} doesn't fit in a byte", value)));
+                }
+                self.emit_byte(value as u8)
+            }
+            _ if self.macros.contains_key(text) => self.expand_macro(text),
+            _ if is_identifier(text) => self.emit_address_op(0x2000, text),
+            _ => Err(self.error(format!("unexpected '{}'", text))),
+        }
+    }
+
+    fn define_const(&mut self, name: String, value: f64) -> Result<(), Chip9Error> {
+        if self.labels.contains_key(&name) {
+            return Err(self.error(format!("'{}' is already defined", name)));
+        }
+        self.consts.insert(name, value);
+        Ok(())
+    }
+
+    fn define_macro(&mut self) -> Result<(), Chip9Error> {
+        let name = self.expect()?;
+        let mut args = Vec::new();
+        loop {
+            let arg = self.expect()?;
+            if arg == "{" {
+                break;
+            }
+            args.push(arg);
+        }
+        let body = self.block()?;
+        self.macros.insert(name, Macro { args, body });
+        Ok(())
+    }
+
+    fn expand_macro(&mut self, name: &str) -> Result<(), Chip9Error> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(self.error(format!("too many macro expansions, is '{}' recursive?", name)));
+        }
+
+        let argc = self.macros[name].args.len();
+        let values = (0..argc).map(|_| self.expect()).collect::<Result<Vec<String>, _>>()?;
+        let line = self.line;
+        let mac = &self.macros[name];
+        let expanded: Vec<Token> = mac.body.iter()
+            .map(|token| {
+                let text = mac.args.iter()
+                    .position(|arg| *arg == token.text)
+                    .map_or_else(|| token.text.clone(), |i| values[i].clone());
+                Token { text, line }
+            })
+            .collect();
+
+        for token in expanded.into_iter().rev() {
+            self.tokens.push_front(token);
+        }
+        Ok(())
+    }
+
+    fn calc(&mut self) -> Result<f64, Chip9Error> {
+        let tokens = self.block()?;
+        let here = self.here as f64;
+        Calc::new(&tokens, |name| {
+            if name == "HERE" {
+                return Some(here);
+            }
+            self.consts.get(name).copied().or_else(|| self.labels.get(name).map(|&addr| addr as f64))
+        })
+        .evaluate()
+        .map_err(|e| self.error(e))
+    }
+
+    fn register_op(&mut self, code: u16) -> Result<(), Chip9Error> {
+        let x = self.expect_register()?;
+        self.emit(code | x << 8)
+    }
+
+    // `save vx`/`load vx`, or the XO-CHIP `save vx - vy`/`load vx - vy`
+    fn transfer(&mut self, code: u16, range_code: u16) -> Result<(), Chip9Error> {
+        let x = self.expect_register()?;
+        if self.peek_is("-") {
+            self.expect()?;
+            let y = self.expect_register()?;
+            return self.emit(range_code | x << 8 | y << 4);
+        }
+        self.emit(code | x << 8)
+    }
+
+    fn index_statement(&mut self) -> Result<(), Chip9Error> {
+        let op = self.expect()?;
+        match op.as_str() {
+            ":=" => {
+                let target = self.expect()?;
+                match target.as_str() {
+                    "hex" => self.register_op(0xF029),
+                    "bighex" => self.register_op(0xF030),
+                    "long" => {
+                        let target = self.expect()?;
+                        self.emit(0xF000)?;
+                        match self.value(&target) {
+                            Some(addr) => {
+                                let addr = self.to_u16(addr)?;
+                                self.emit(addr)
+                            }
+                            None => {
+                                self.emit(0x0000)?;
+                                self.add_fixup(self.here as u16 - 2, &target, FixupKind::Addr16)
+                            }
+                        }
+                    }
+                    _ => self.emit_address_op(0xA000, &target),
+                }
+            }
+            "+=" => self.register_op(0xF01E),
+            _ => Err(self.error(format!("unexpected '{}' after 'i'", op))),
+        }
+    }
+
+    fn register_statement(&mut self, reg: &str) -> Result<(), Chip9Error> {
+        let x = self.register(reg).unwrap() as u16;
+        let op = self.expect()?;
+
+        // Register sources taking a keyword instead of an operand
+        if op == ":=" {
+            let code = if self.peek_is("random") {
+                self.expect()?;
+                let mask = self.expect_byte()?;
+                Some(0xC000 | mask)
+            } else if self.peek_is("key") {
+                self.expect()?;
+                Some(0xF00A)
+            } else if self.peek_is("delay") {
+                self.expect()?;
+                Some(0xF007)
+            } else {
+                None
+            };
+            if let Some(code) = code {
+                return self.emit(code | x << 8);
+            }
+        }
+
+        let code = match (op.as_str(), self.expect_operand()?) {
+            (":=", Operand::Value(kk)) => 0x6000 | kk as u16,
+            (":=", Operand::Reg(y)) => 0x8000 | (y as u16) << 4,
+            ("+=", Operand::Value(kk)) => 0x7000 | kk as u16,
+            ("+=", Operand::Reg(y)) => 0x8004 | (y as u16) << 4,
+            ("-=", Operand::Value(kk)) => 0x7000 | (kk as u8).wrapping_neg() as u16,
+            ("-=", Operand::Reg(y)) => 0x8005 | (y as u16) << 4,
+            ("=-", Operand::Reg(y)) => 0x8007 | (y as u16) << 4,
+            ("|=", Operand::Reg(y)) => 0x8001 | (y as u16) << 4,
+            ("&=", Operand::Reg(y)) => 0x8002 | (y as u16) << 4,
+            ("^=", Operand::Reg(y)) => 0x8003 | (y as u16) << 4,
+            (">>=", Operand::Reg(y)) => 0x8006 | (y as u16) << 4,
+            ("<<=", Operand::Reg(y)) => 0x800E | (y as u16) << 4,
+            (op, _) => return Err(self.error(format!("unsupported operation '{} {}'", reg, op))),
+        };
+        self.emit(code | x << 8)
+    }
+
+    fn if_statement(&mut self) -> Result<(), Chip9Error> {
+        let skip = self.condition()?;
+        let keyword = self.expect()?;
+        match keyword.as_str() {
+            // The skip jumps over the statement when the condition fails
+            "then" => self.emit(negate(skip)),
+            // The skip jumps over the jump to `else`/`end` when the condition holds
+            "begin" => {
+                self.emit(skip)?;
+                self.branches.push(self.here()?);
+                self.emit(JUMP_PLACEHOLDER)
+            }
+            _ => Err(self.error(format!("expected 'then' or 'begin', found '{}'", keyword))),
+        }
+    }
+
+    // Emits the setup comparisons need and returns the skip instruction taken when the condition holds
+    fn condition(&mut self) -> Result<u16, Chip9Error> {
+        let x = self.expect_register()?;
+        let op = self.expect()?;
+        match op.as_str() {
+            "key" => return Ok(0xE09E | x << 8),
+            "-key" => return Ok(0xE0A1 | x << 8),
+            _ => (),
+        }
+
+        let operand = self.expect_operand()?;
+        match (op.as_str(), operand) {
+            ("==", Operand::Value(kk)) => Ok(0x3000 | x << 8 | kk as u16),
+            ("!=", Operand::Value(kk)) => Ok(0x4000 | x << 8 | kk as u16),
+            ("==", Operand::Reg(y)) => Ok(0x5000 | x << 8 | (y as u16) << 4),
+            ("!=", Operand::Reg(y)) => Ok(0x9000 | x << 8 | (y as u16) << 4),
+            (op @ ("<" | ">" | "<=" | ">="), operand) => {
+                // vf := y, then vf =- x (flag set when x >= y) or vf -= x (flag set when x <= y)
+                self.emit(match operand {
+                    Operand::Value(kk) => 0x6F00 | kk as u16,
+                    Operand::Reg(y) => 0x8F00 | (y as u16) << 4,
+                })?;
+                let flag_set = match op {
+                    ">=" | "<" => {
+                        self.emit(0x8F07 | x << 4)?;
+                        op == ">="
+                    }
+                    _ => {
+                        self.emit(0x8F05 | x << 4)?;
+                        op == "<="
+                    }
+                };
+                Ok(0x3F00 | flag_set as u16)
+            }
+            (op, _) => Err(self.error(format!("unsupported comparison '{}'", op))),
+        }
+    }
+}
+
+// Skip taken when the condition of `skip` fails
+fn negate(skip: u16) -> u16 {
+    match skip & 0xF000 {
+        0x3000 => skip ^ 0x7000, // 3xkk <-> 4xkk
+        0x4000 => skip ^ 0x7000,
+        0x5000 => skip ^ 0xC000, // 5xy0 <-> 9xy0
+        0x9000 => skip ^ 0xC000,
+        _ => skip ^ (0x9E ^ 0xA1), // Ex9E <-> ExA1
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.chars().next().is_some_and(|c| c.is_alphabetic() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip9::cpu::OpCode;
+
+    // Decodes the assembled ROM back into mnemonics
+    fn listing(source: &str) -> Vec<String> {
+        let rom = assemble(source).unwrap().rom;
+        let word = |at: usize| u16::from_be_bytes([rom[at], rom.get(at + 1).copied().unwrap_or(0)]);
+        let mut listing = Vec::new();
+        let mut offset = 0;
+        while offset < rom.len() {
+            let code = word(offset);
+            let operand = if offset + 2 < rom.len() { word(offset + 2) } else { 0 };
+            listing.push(OpCode::decode(code, operand).unwrap().to_string());
+            offset += OpCode::size(code) as usize;
+        }
+        listing
+    }
+
+    fn error(source: &str) -> (usize, String) {
+        match assemble(source) {
+            Err(Chip9Error::AssemblyError(line, message)) => (line, message),
+            Err(e) => panic!("unexpected error: {e}"),
+            Ok(_) => panic!("assembled without errors"),
+        }
+    }
+
+    #[test]
+    fn jumps_to_main_unless_it_comes_first() {
+        assert_eq!(listing(": main exit"), ["EXIT"]);
+        assert_eq!(listing(": helper return : main helper"), ["JP 0x204", "RET", "CALL 0x202"]);
+    }
+
+    #[test]
+    fn if_then() {
+        assert_eq!(listing(": main if v0 == 5 then v1 := 2"), ["SNE V0, 0x05", "LD V1, 0x02"]);
+        assert_eq!(listing(": main if v0 key then clear"), ["SKNP V0", "CLS"]);
+    }
+
+    #[test]
+    fn if_begin_else_end() {
+        assert_eq!(
+            listing(": main if v0 != v1 begin v2 := 1 else v2 := 2 end clear"),
+            ["SNE V0, V1", "JP 0x208", "LD V2, 0x01", "JP 0x20A", "LD V2, 0x02", "CLS"],
+        );
+    }
+
+    #[test]
+    fn comparison_goes_through_vf() {
+        assert_eq!(
+            listing(": main if v3 < 8 then exit"),
+            ["LD VF, 0x08", "SUBN VF, V3", "SNE VF, 0x00", "EXIT"],
+        );
+    }
+
+    #[test]
+    fn loop_while_again() {
+        assert_eq!(
+            listing(": main loop v0 += 1 while v0 != 10 again exit"),
+            ["ADD V0, 0x01", "SNE V0, 0x0A", "JP 0x208", "JP 0x200", "EXIT"],
+        );
+    }
+
+    #[test]
+    fn program_may_end_at_the_last_address() {
+        let rom = assemble(": main exit :org 0xFFFC jump helper 0x12 0x34 :org 0x300 : helper return").unwrap().rom;
+        assert_eq!(rom.len(), 0x10000 - PROGRAM_START as usize);
+        assert_eq!(rom[rom.len() - 4..], [0x13, 0x00, 0x12, 0x34]);
+
+        assert_eq!(error(": main exit\n:org 0xFFFE\n0x12 0x34\n0x56"), (4, "program doesn't fit in memory".to_string()));
+        assert_eq!(error(": main exit\n:org 0xFFFF\nclear"), (3, "program doesn't fit in memory".to_string()));
+    }
+
+    #[test]
+    fn next_labels_the_operand_byte() {
+        assert_eq!(listing(": main i := target :next target v0 := 0"), ["LD I, 0x203", "LD V0, 0x00"]);
+    }
+
+    #[test]
+    fn next_rejects_data() {
+        let message = "':next target' must be followed by an instruction, not data".to_string();
+        assert_eq!(error(": main\n:next target\n:byte 5\nv0 := 0"), (3, message.clone()));
+        assert_eq!(error(": main\n:next target\n0x12"), (3, message));
+    }
+
+    #[test]
+    fn calc_evaluates_right_to_left() {
+        let source = ":calc a { 2 * 3 + 1 } :calc b { 10 - 4 - 3 } :calc c { ( 10 - 4 ) - 3 } : main v0 := a v1 := b v2 := c";
+        assert_eq!(listing(source), ["LD V0, 0x08", "LD V1, 0x09", "LD V2, 0x03"]);
+    }
+
+    #[test]
+    fn macros_substitute_arguments() {
+        let source = ":macro set reg val { reg := val } : main set v3 7 set v4 0x20";
+        assert_eq!(listing(source), ["LD V3, 0x07", "LD V4, 0x20"]);
+    }
+
+    #[test]
+    fn aliases_name_registers() {
+        assert_eq!(listing(":alias score v5 : main score += 2 score := v1"), ["ADD V5, 0x02", "LD V5, V1"]);
+    }
+
+    #[test]
+    fn bytes_are_emitted_as_is() {
+        assert_eq!(assemble(": main exit :byte 0x12 :byte { 3 + 4 } 255").unwrap().rom, [0x00, 0xFD, 0x12, 0x07, 0xFF]);
+    }
+
+    #[test]
+    fn long_index_fixes_up_forward_labels() {
+        let program = assemble(": main i := long data exit :org 0x1234 : data 0xAB").unwrap();
+        assert_eq!(program.rom[..6], [0xF0, 0x00, 0x12, 0x34, 0x00, 0xFD]);
+        assert_eq!(program.rom.last(), Some(&0xAB));
+        assert_eq!(program.symbols["data"], 0x1234);
+    }
+
+    #[test]
+    fn undefined_label_is_an_error() {
+        assert_eq!(error(": main\nexit\njump nowhere"), (3, "undefined label 'nowhere'".to_string()));
+    }
+
+    #[test]
+    fn bad_register_is_an_error() {
+        assert_eq!(error(": main sprite v0 v16 5"), (1, "expected a register, found 'v16'".to_string()));
+    }
+
+    #[test]
+    fn unterminated_blocks_are_errors() {
+        assert_eq!(error(": main loop v0 += 1").1, "'loop' without matching 'again'");
+        assert_eq!(error(": main if v0 == 1 begin v1 := 2").1, "'begin' without matching 'end'");
+        assert_eq!(error(": main again").1, "'again' without 'loop'");
+        assert_eq!(error(": main end").1, "'end' without 'begin'");
+    }
+}
@@ -0,0 +1,133 @@
+use super::lexer::Token;
+
+/*
+    `:calc` expressions follow Octo: there is no operator precedence and
+    binary operators are evaluated right to left, so `2 * 3 + 1` is `2 * (3 + 1)`.
+    Parentheses group as usual.
+*/
+const UNARY: [&str; 13] = ["-", "~", "!", "sin", "cos", "tan", "exp", "log", "abs", "sqrt", "sign", "ceil", "floor"];
+const BINARY: [&str; 19] = [
+    "-", "+", "*", "/", "%", "pow", "min", "max", "&", "|", "^", "<<", ">>",
+    "<", ">", "<=", ">=", "==", "!=",
+];
+
+pub struct Calc<'a, F: Fn(&str) -> Option<f64>> {
+    tokens: &'a [Token],
+    pos: usize,
+    lookup: F,
+}
+
+impl<'a, F: Fn(&str) -> Option<f64>> Calc<'a, F> {
+    /// `lookup` resolves constants and labels by name
+    pub fn new(tokens: &'a [Token], lookup: F) -> Self {
+        Self { tokens, pos: 0, lookup }
+    }
+
+    pub fn evaluate(mut self) -> Result<f64, String> {
+        let value = self.expression()?;
+        match self.tokens.get(self.pos) {
+            Some(token) => Err(format!("unexpected '{}' in expression", token.text)),
+            None => Ok(value),
+        }
+    }
+
+    fn next(&mut self) -> Result<&'a str, String> {
+        let token = self.tokens.get(self.pos).ok_or("unexpected end of expression")?;
+        self.pos += 1;
+        Ok(&token.text)
+    }
+
+    fn expression(&mut self) -> Result<f64, String> {
+        let left = self.term()?;
+        match self.tokens.get(self.pos).map(|token| token.text.as_str()) {
+            Some(op) if BINARY.contains(&op) => {
+                self.pos += 1;
+                let right = self.expression()?;
+                Ok(binary(op, left, right))
+            }
+            _ => Ok(left),
+        }
+    }
+
+    fn term(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        if token == "(" {
+            let value = self.expression()?;
+            return match self.next()? {
+                ")" => Ok(value),
+                other => Err(format!("expected ')', found '{}'", other)),
+            };
+        }
+        if UNARY.contains(&token) {
+            let value = self.term()?;
+            return Ok(unary(token, value));
+        }
+        match token {
+            "PI" => Ok(std::f64::consts::PI),
+            "E" => Ok(std::f64::consts::E),
+            _ => parse_number(token)
+                .map(|value| value as f64)
+                .or_else(|| (self.lookup)(token))
+                .ok_or_else(|| format!("undefined name '{}' in expression", token)),
+        }
+    }
+}
+
+fn unary(op: &str, value: f64) -> f64 {
+    match op {
+        "-" => -value,
+        "~" => !(value as i64) as f64,
+        "!" => (value == 0.0) as i64 as f64,
+        "sin" => value.sin(),
+        "cos" => value.cos(),
+        "tan" => value.tan(),
+        "exp" => value.exp(),
+        "log" => value.ln(),
+        "abs" => value.abs(),
+        "sqrt" => value.sqrt(),
+        "sign" => value.signum(),
+        "ceil" => value.ceil(),
+        _ => value.floor(),
+    }
+}
+
+fn binary(op: &str, left: f64, right: f64) -> f64 {
+    let (l, r) = (left as i64, right as i64);
+    match op {
+        "-" => left - right,
+        "+" => left + right,
+        "*" => left * right,
+        "/" => left / right,
+        "%" => left % right,
+        "pow" => left.powf(right),
+        "min" => left.min(right),
+        "max" => left.max(right),
+        "&" => (l & r) as f64,
+        "|" => (l | r) as f64,
+        "^" => (l ^ r) as f64,
+        "<<" => (l << r) as f64,
+        ">>" => (l >> r) as f64,
+        "<" => (left < right) as i64 as f64,
+        ">" => (left > right) as i64 as f64,
+        "<=" => (left <= right) as i64 as f64,
+        ">=" => (left >= right) as i64 as f64,
+        "==" => (left == right) as i64 as f64,
+        _ => (left != right) as i64 as f64,
+    }
+}
+
+/// Parses decimal, `0x` hex and `0b` binary literals, optionally negative
+pub fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+        i64::from_str_radix(bin, 2).ok()?
+    } else {
+        digits.parse().ok()?
+    };
+    Some(if negative { -value } else { value })
+}
@@ -0,0 +1,17 @@
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub text: String,
+    pub line: usize,
+}
+
+/// Splits Octo source into whitespace separated tokens, dropping `#` comments
+pub fn tokenize(source: &str) -> Vec<Token> {
+    source.lines()
+        .enumerate()
+        .flat_map(|(i, line)| {
+            let code = line.split('#').next().unwrap_or_default();
+            code.split_whitespace()
+                .map(move |text| Token { text: text.to_string(), line: i + 1 })
+        })
+        .collect()
+}
@@ -0,0 +1,221 @@
+use std::fmt;
+use std::str::FromStr;
+use std::sync::{
+    atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering},
+    Arc,
+};
+use std::time::Duration;
+
+use rodio::{OutputStream, OutputStreamBuilder, Source};
+
+const SAMPLE_RATE: u32 = 44_100;
+const RAMP_TIME: f32 = 0.005; // fade in/out time in seconds, avoids clicks on start and stop
+const PATTERN_BITS: usize = 128;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Waveform {
+    #[default]
+    Square,
+    Triangle,
+    Sine,
+}
+
+impl Waveform {
+    pub const ALL: [Waveform; 3] = [Waveform::Square, Waveform::Triangle, Waveform::Sine];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Waveform::Square => "square",
+            Waveform::Triangle => "triangle",
+            Waveform::Sine => "sine",
+        }
+    }
+
+    // phase in [0, 1)
+    fn sample(&self, phase: f32) -> f32 {
+        match self {
+            Waveform::Square => if phase < 0.5 { 1.0 } else { -1.0 },
+            Waveform::Triangle => 1.0 - 4.0 * (phase - 0.5).abs(),
+            Waveform::Sine => (phase * std::f32::consts::TAU).sin(),
+        }
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Waveform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Waveform::ALL.into_iter()
+            .find(|waveform| waveform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown waveform '{}', expected one of: square, triangle, sine", s))
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioConfig {
+    pub waveform: Waveform,
+    pub frequency: f32, // Hz
+    pub volume: f32, // 0.0 - 1.0
+}
+
+impl Default for AudioConfig {
+    fn default() -> Self {
+        Self { waveform: Waveform::Square, frequency: 440.0, volume: 0.25 }
+    }
+}
+
+/// XO-CHIP audio pattern, played instead of the tone once a program loads one
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AudioPattern {
+    pub samples: [u8; PATTERN_BITS / 8], // 1-bit samples, most significant bit first
+    pub rate: f32, // samples per second
+}
+
+/// Plays the beep of a frontend
+pub trait AudioSink {
+    fn set_playing(&mut self, playing: bool);
+    fn toggle_mute(&mut self);
+    /// Pattern to loop while playing, None plays the configured tone
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>);
+}
+
+// State shared between the emulator and the audio thread
+struct ToneState {
+    playing: AtomicBool,
+    muted: AtomicBool,
+    pattern: [AtomicU64; 2], // samples, the first one in the top bit of the first half
+    pattern_rate: AtomicU32, // f32 bits, 0 when there's no pattern
+}
+
+/// Plays a tone while the sound timer is running.
+/// Falls back to a silent sink when no audio device is available.
+pub struct Beeper {
+    state: Arc<ToneState>,
+    _stream: Option<OutputStream>,
+}
+
+impl Beeper {
+    pub fn new(config: AudioConfig) -> Self {
+        let mut beeper = Self::silent();
+
+        match OutputStreamBuilder::open_default_stream() {
+            Ok(mut stream) => {
+                stream.log_on_drop(false);
+                stream.mixer().add(Tone::new(config, beeper.state.clone()));
+                beeper._stream = Some(stream);
+            }
+            Err(e) => eprintln!("Audio unavailable, continuing without sound: {e}"),
+        }
+
+        beeper
+    }
+
+    /// Beeper that never outputs anything
+    pub fn silent() -> Self {
+        let state = Arc::new(ToneState {
+            playing: AtomicBool::new(false),
+            muted: AtomicBool::new(false),
+            pattern: [AtomicU64::new(0), AtomicU64::new(0)],
+            pattern_rate: AtomicU32::new(0),
+        });
+
+        Self { state, _stream: None }
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        self.state.playing.store(playing, Ordering::Relaxed);
+    }
+
+    pub fn toggle_mute(&self) {
+        self.state.muted.fetch_xor(true, Ordering::Relaxed);
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.state.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_pattern(&self, pattern: Option<AudioPattern>) {
+        let (samples, rate) = pattern.map_or(([0; PATTERN_BITS / 8], 0.0), |pattern| (pattern.samples, pattern.rate));
+        for (half, bytes) in self.state.pattern.iter().zip(samples.chunks(8)) {
+            half.store(u64::from_be_bytes(bytes.try_into().unwrap()), Ordering::Relaxed);
+        }
+        self.state.pattern_rate.store(rate.to_bits(), Ordering::Relaxed);
+    }
+}
+
+impl AudioSink for Beeper {
+    fn set_playing(&mut self, playing: bool) {
+        Beeper::set_playing(self, playing);
+    }
+
+    fn toggle_mute(&mut self) {
+        Beeper::toggle_mute(self);
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        Beeper::set_pattern(self, pattern);
+    }
+}
+
+// Endless mono source, its gain ramps towards the volume while playing and towards 0 otherwise
+struct Tone {
+    config: AudioConfig,
+    state: Arc<ToneState>,
+    phase: f32,
+    pattern_pos: f32, // in samples of the pattern
+    gain: f32,
+}
+
+impl Tone {
+    fn new(config: AudioConfig, state: Arc<ToneState>) -> Self {
+        Self { config, state, phase: 0.0, pattern_pos: 0.0, gain: 0.0 }
+    }
+}
+
+impl Iterator for Tone {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let audible = self.state.playing.load(Ordering::Relaxed) && !self.state.muted.load(Ordering::Relaxed);
+        let target = if audible { self.config.volume.clamp(0.0, 1.0) } else { 0.0 };
+        let step = 1.0 / (RAMP_TIME * SAMPLE_RATE as f32);
+        self.gain = if self.gain < target { (self.gain + step).min(target) } else { (self.gain - step).max(target) };
+
+        let rate = f32::from_bits(self.state.pattern_rate.load(Ordering::Relaxed));
+        let sample = if rate > 0.0 {
+            let bit = self.pattern_pos as usize;
+            let half = self.state.pattern[bit / 64].load(Ordering::Relaxed);
+            self.pattern_pos = (self.pattern_pos + rate / SAMPLE_RATE as f32) % PATTERN_BITS as f32;
+            if (half >> (63 - bit % 64)) & 1 == 1 { 1.0 } else { -1.0 }
+        } else {
+            let sample = self.config.waveform.sample(self.phase);
+            self.phase = (self.phase + self.config.frequency / SAMPLE_RATE as f32).fract();
+            sample
+        };
+        Some(sample * self.gain)
+    }
+}
+
+impl Source for Tone {
+    fn current_span_len(&self) -> Option<usize> {
+        None
+    }
+
+    fn channels(&self) -> u16 {
+        1
+    }
+
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
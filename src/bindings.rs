@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Names of the host keys that can be bound, matched without regard to case.
+/// Frontends translate their own key codes to these
+pub const KEY_NAMES: &[&str] = &[
+    "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+    "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    "NumPad0", "NumPad1", "NumPad2", "NumPad3", "NumPad4",
+    "NumPad5", "NumPad6", "NumPad7", "NumPad8", "NumPad9",
+    "NumPadDot", "NumPadSlash", "NumPadAsterisk", "NumPadMinus", "NumPadPlus", "NumPadEnter",
+    "Up", "Down", "Left", "Right", "Space", "Enter", "Tab",
+    "Apostrophe", "Backquote", "Backslash", "Comma", "Equal", "LeftBracket",
+    "Minus", "Period", "RightBracket", "Semicolon", "Slash",
+];
+
+/// Host keys taken by the emulator hotkeys in every frontend, they can't be bound to CHIP-8 keys
+pub const COMMAND_KEY_NAMES: &[&str] = &["M", "P", "T", "Minus", "Equal", "Comma", "Period"];
+
+/*
+    The CHIP-8 keypad, as laid out on the COSMAC VIP:
+
+        1 2 3 C
+        4 5 6 D
+        7 8 9 E
+        A 0 B F
+*/
+const QWERTY: [(&str, u8); 16] = [
+    ("1", 0x1), ("2", 0x2), ("3", 0x3), ("4", 0xC),
+    ("Q", 0x4), ("W", 0x5), ("E", 0x6), ("R", 0xD),
+    ("A", 0x7), ("S", 0x8), ("D", 0x9), ("F", 0xE),
+    ("Z", 0xA), ("X", 0x0), ("C", 0xB), ("V", 0xF),
+];
+
+// Digits on their own numbers, A-F on the operator keys around them
+const NUMPAD: [(&str, u8); 16] = [
+    ("NumPad0", 0x0), ("NumPad1", 0x1), ("NumPad2", 0x2), ("NumPad3", 0x3),
+    ("NumPad4", 0x4), ("NumPad5", 0x5), ("NumPad6", 0x6), ("NumPad7", 0x7),
+    ("NumPad8", 0x8), ("NumPad9", 0x9), ("NumPadSlash", 0xA), ("NumPadAsterisk", 0xB),
+    ("NumPadMinus", 0xC), ("NumPadPlus", 0xD), ("NumPadEnter", 0xE), ("NumPadDot", 0xF),
+];
+
+// The VIP keypad on the numpad by position, so 7 is 1 and 0 is A
+const VIP: [(&str, u8); 16] = [
+    ("NumPad7", 0x1), ("NumPad8", 0x2), ("NumPad9", 0x3), ("NumPadSlash", 0xC),
+    ("NumPad4", 0x4), ("NumPad5", 0x5), ("NumPad6", 0x6), ("NumPadAsterisk", 0xD),
+    ("NumPad1", 0x7), ("NumPad2", 0x8), ("NumPad3", 0x9), ("NumPadMinus", 0xE),
+    ("NumPad0", 0xA), ("NumPadDot", 0x0), ("NumPadEnter", 0xB), ("NumPadPlus", 0xF),
+];
+
+/// Built-in key layouts
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BindingPreset {
+    /// 1234/QWER/ASDF/ZXCV, in the shape of the VIP keypad
+    #[default]
+    Qwerty,
+    /// Each number on its numpad key, A-F on / * - + Enter .
+    Numpad,
+    /// The VIP keypad on the numpad by position
+    Vip,
+}
+
+impl BindingPreset {
+    pub const ALL: [BindingPreset; 3] = [BindingPreset::Qwerty, BindingPreset::Numpad, BindingPreset::Vip];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BindingPreset::Qwerty => "qwerty",
+            BindingPreset::Numpad => "numpad",
+            BindingPreset::Vip => "vip",
+        }
+    }
+
+    fn keys(&self) -> &'static [(&'static str, u8); 16] {
+        match self {
+            BindingPreset::Qwerty => &QWERTY,
+            BindingPreset::Numpad => &NUMPAD,
+            BindingPreset::Vip => &VIP,
+        }
+    }
+}
+
+impl fmt::Display for BindingPreset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for BindingPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        BindingPreset::ALL.into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = BindingPreset::ALL.iter().map(BindingPreset::name).collect();
+                format!("unknown bindings preset '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// Host keys bound to CHIP-8 keys, any number of host keys per CHIP-8 key
+#[derive(Clone, Debug)]
+pub struct Bindings {
+    keys: HashMap<&'static str, u8>, // names from KEY_NAMES
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::preset(BindingPreset::default())
+    }
+}
+
+impl Bindings {
+    pub fn preset(preset: BindingPreset) -> Self {
+        Self { keys: preset.keys().iter().copied().collect() }
+    }
+
+    /// Replaces the host keys of a CHIP-8 key, an empty list unbinds it
+    pub fn bind(&mut self, chip9_key: u8, host_keys: &[String]) -> Result<(), String> {
+        if chip9_key > 0xF {
+            return Err(format!("no CHIP-8 key {:X}", chip9_key));
+        }
+        let names = host_keys.iter()
+            .map(|host_key| match key_name(host_key) {
+                Some(name) if COMMAND_KEY_NAMES.contains(&name) => Err(format!("key '{}' is an emulator hotkey", name)),
+                Some(name) => Ok(name),
+                None => Err(format!("unknown key '{}'", host_key)),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        self.keys.retain(|_, key| *key != chip9_key);
+        for name in names {
+            self.keys.insert(name, chip9_key);
+        }
+        Ok(())
+    }
+
+    /// CHIP-8 key bound to a host key, by its name in `KEY_NAMES`
+    pub fn get(&self, host_key: &str) -> Option<u8> {
+        key_name(host_key).and_then(|name| self.keys.get(name).copied())
+    }
+}
+
+fn key_name(name: &str) -> Option<&'static str> {
+    KEY_NAMES.iter().copied().find(|known| known.eq_ignore_ascii_case(name))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hotkeys_cannot_be_bound() {
+        let mut bindings = Bindings::default();
+        for name in COMMAND_KEY_NAMES {
+            assert_eq!(bindings.bind(0x5, &[name.to_lowercase()]), Err(format!("key '{}' is an emulator hotkey", name)));
+        }
+        assert_eq!(bindings.get("W"), Some(0x5));
+    }
+
+    #[test]
+    fn binding_replaces_host_keys() {
+        let mut bindings = Bindings::default();
+        bindings.bind(0x5, &["up".to_string(), "Space".to_string()]).unwrap();
+        assert_eq!(bindings.get("Up"), Some(0x5));
+        assert_eq!(bindings.get("space"), Some(0x5));
+        assert_eq!(bindings.get("W"), None);
+    }
+}
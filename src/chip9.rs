@@ -1,23 +1,42 @@
 pub mod cpu;
 mod display;
+mod fault;
 mod keyboard;
+mod quirks;
+mod state;
+mod trace;
 
 use std::fs::File;
+use std::io::{BufWriter, Read, Write};
+use std::path::Path;
 
+use crate::audio::AudioPattern;
 use crate::errors::Chip9Error;
-use cpu::CPU;
-pub use display::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::palette::Palette;
+use crate::screenshot::{self, ImageFormat};
+use cpu::{CPU, SplitMix64};
+pub use cpu::RandomSource;
+use state::{StateReader, StateWriter};
+pub use display::{Display, DISPLAY_HEIGHT, DISPLAY_PLANES, DISPLAY_WIDTH, LORES_HEIGHT, LORES_WIDTH};
+pub use fault::{FaultAction, FaultContext, FaultPolicy};
 pub use keyboard::Keyboard;
+pub use quirks::{IndexIncrement, Preset, Quirks};
+pub use state::STATE_VERSION;
+pub use trace::{diff as diff_traces, PcRange, Trace, TraceFilter};
+
+pub const FRAME_RATE: u32 = 60;
+pub const DEFAULT_IPF: u32 = 11; // instructions per frame, ~700Hz
 
 pub struct Chip9 {
     cpu: CPU,
     pub display: Display, // fixme
     pub keyboard: Keyboard,
+    trace: Option<Trace>,
 }
 
 impl Chip9 {
-    pub fn new() -> Self {
-        let cpu = CPU::new();
+    pub fn new(quirks: Quirks) -> Self {
+        let cpu = CPU::new(quirks);
         let display = Display::new();
         let keyboard = Keyboard::new();
 
@@ -25,18 +44,376 @@ impl Chip9 {
             cpu,
             display,
             keyboard,
+            trace: None,
         }
     }
 
     pub fn tick(&mut self) -> Result<(), Chip9Error> {
+        if let Some(trace) = &mut self.trace {
+            trace.record(&self.cpu).map_err(Chip9Error::TraceIoError)?;
+        }
         self.cpu.execute(&mut self.display, &mut self.keyboard)
     }
 
+    /// Logs every executed instruction to `trace`, buffered output is flushed when the machine is dropped
+    pub fn set_trace(&mut self, trace: Trace) {
+        self.trace = Some(trace);
+    }
+
+    /// Runs one frame: `ipf` instructions, fewer if the program exits, followed by the vertical blank
+    pub fn run_frame(&mut self, ipf: u32) -> Result<(), Chip9Error> {
+        for _ in 0..ipf {
+            if self.is_halted() {
+                break;
+            }
+            self.tick()?;
+        }
+        self.vblank();
+        Ok(())
+    }
+
+    /// Runs a frame like `run_frame` without tracing it, for frames being executed a second time
+    pub fn replay_frame(&mut self, ipf: u32) -> Result<(), Chip9Error> {
+        let trace = self.trace.take();
+        let result = self.run_frame(ipf);
+        self.trace = trace;
+        result
+    }
+
+    /// Sets what happens when the program misuses the stack or runs out of memory
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.cpu.set_fault_policy(policy);
+    }
+
+    /// Makes CXKK draw a reproducible sequence
+    pub fn set_seed(&mut self, seed: u64) {
+        self.cpu.set_rng(Box::new(SplitMix64::new(seed)));
+    }
+
+    /// Replaces the source of CXKK random bytes, its state is kept in save states
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.cpu.set_rng(rng);
+    }
+
+    /// Read-only view of the CPU state
+    pub fn cpu(&self) -> &CPU {
+        &self.cpu
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.cpu.sound_timer()
+    }
+
+    /// XO-CHIP audio pattern the beeper plays instead of its tone, if the program loaded one
+    pub fn audio_pattern(&self) -> Option<AudioPattern> {
+        self.cpu.audio_pattern()
+    }
+
+    /// Has the program exited?
+    pub fn is_halted(&self) -> bool {
+        self.cpu.is_halted()
+    }
+
+    /// Should be called once per 60Hz frame
+    pub fn vblank(&mut self) {
+        self.cpu.vblank();
+    }
+
     pub fn load_program(&mut self, file: &File) -> Result<(), Box<dyn std::error::Error>> {
         self.cpu.load_program(file)
     }
 
-    pub fn shutdown(&mut self) { // should be called only on started
-        self.cpu.shutdown();
+    /// Loads a program already read into memory, e.g. one built by the assembler
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.cpu.load_rom(rom)
+    }
+
+    /// Writes a snapshot of the whole machine
+    pub fn save_state(&self, writer: &mut impl Write) -> Result<(), Chip9Error> {
+        writer.write_all(&self.snapshot()).map_err(Chip9Error::StateIoError)
+    }
+
+    /// Saves the display as an image in `palette`, scaled by `scale`. The format follows
+    /// the extension: .png, .pgm, .pbm or ASCII art otherwise
+    pub fn save_screenshot(&self, path: &Path, palette: &Palette, scale: usize) -> Result<(), Chip9Error> {
+        let file = File::create(path).map_err(Chip9Error::ScreenshotError)?;
+        let mut output = BufWriter::new(file);
+        screenshot::write(&self.display, ImageFormat::from_path(path), palette, scale, &mut output)
+            .and_then(|()| output.flush())
+            .map_err(Chip9Error::ScreenshotError)
+    }
+
+    /// Restores a snapshot written by `save_state`, the machine is left untouched on error
+    pub fn load_state(&mut self, reader: &mut impl Read) -> Result<(), Chip9Error> {
+        let mut data = Vec::new();
+        reader.read_to_end(&mut data).map_err(Chip9Error::StateIoError)?;
+
+        let mut state = StateReader::with_header(&data)?;
+        let expected = self.snapshot().len() - (data.len() - state.remaining());
+        if state.remaining() != expected {
+            return Err(Chip9Error::InvalidState(format!("expected {} bytes of machine state, found {}", expected, state.remaining())));
+        }
+
+        self.cpu.load_state(&mut state)?;
+        self.display.load_state(&mut state)?;
+        self.keyboard.load_state(&mut state)
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        let mut state = StateWriter::with_header();
+        self.cpu.save_state(&mut state);
+        self.display.save_state(&mut state);
+        self.keyboard.save_state(&mut state);
+        state.into_bytes()
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn machine(rom: &[u8]) -> Chip9 {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.set_seed(0);
+        chip9.load_rom(rom).unwrap();
+        chip9
+    }
+
+    fn state(chip9: &Chip9) -> Vec<u8> {
+        let mut state = Vec::new();
+        chip9.save_state(&mut state).unwrap();
+        state
+    }
+
+    #[test]
+    fn state_with_bad_stack_pointer_is_rejected() {
+        let mut chip9 = machine(&[0x60, 0x2A, 0x12, 0x02]); // V0 := 42, loop
+        let mut corrupt = state(&chip9);
+        corrupt[26] = 17; // stack pointer, after the header, registers, I and PC
+
+        chip9.tick().unwrap();
+        let before = state(&chip9);
+        assert!(matches!(chip9.load_state(&mut corrupt.as_slice()), Err(Chip9Error::InvalidState(_))));
+        assert_eq!(state(&chip9), before);
+    }
+
+    #[test]
+    fn state_keeps_pending_vblank() {
+        let mut chip9 = Chip9::new(Quirks::COSMAC_VIP);
+        chip9.load_rom(&[0xD0, 0x05, 0x12, 0x02]).unwrap(); // draw once, loop
+        chip9.vblank();
+        let saved = state(&chip9);
+
+        chip9.tick().unwrap();
+        chip9.load_state(&mut saved.as_slice()).unwrap();
+        chip9.tick().unwrap();
+
+        assert_eq!(chip9.cpu().program_counter(), 0x202);
+        assert_eq!(chip9.display.pixel(0, 0), 1);
+    }
+
+    #[test]
+    fn timers_count_down_once_per_frame() {
+        let mut chip9 = machine(&[0x60, 0x05, 0xF0, 0x15, 0xF0, 0x18, 0x12, 0x06]); // DT := ST := 5, loop
+        for _ in 0..100 {
+            chip9.tick().unwrap();
+        }
+        assert_eq!((chip9.cpu().delay_timer(), chip9.sound_timer()), (5, 5));
+
+        chip9.vblank();
+        assert_eq!((chip9.cpu().delay_timer(), chip9.sound_timer()), (4, 4));
+        chip9.run_frame(DEFAULT_IPF).unwrap();
+        assert_eq!((chip9.cpu().delay_timer(), chip9.sound_timer()), (3, 3));
+        for _ in 0..10 {
+            chip9.run_frame(DEFAULT_IPF).unwrap();
+        }
+        assert_eq!((chip9.cpu().delay_timer(), chip9.sound_timer()), (0, 0));
+    }
+
+    // V0-V3 := random bytes, repeat
+    const RANDOM_ROM: [u8; 10] = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF, 0xC3, 0xFF, 0x12, 0x00];
+
+    #[test]
+    fn seed_gives_fixed_random_sequence() {
+        let mut chip9 = machine(&RANDOM_ROM);
+        chip9.set_seed(0);
+        chip9.run_frame(4).unwrap();
+
+        assert_eq!(chip9.cpu().registers()[..4], [0xAF, 0xF4, 0x4F, 0xEC]);
+    }
+
+    #[test]
+    fn random_sequence_survives_state() {
+        let mut chip9 = machine(&RANDOM_ROM);
+        chip9.set_seed(1234);
+        chip9.run_frame(5).unwrap();
+        let saved = state(&chip9);
+
+        chip9.run_frame(20).unwrap();
+        let expected = chip9.cpu().registers();
+        chip9.set_seed(0);
+        chip9.load_state(&mut saved.as_slice()).unwrap();
+        chip9.run_frame(20).unwrap();
+
+        assert_eq!(chip9.cpu().registers(), expected);
+    }
+}
+
+#[cfg(test)]
+mod fault_tests {
+    use super::*;
+
+    fn machine(rom: &[u8], action: FaultAction) -> Chip9 {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(rom).unwrap();
+        chip9.set_fault_policy(FaultPolicy {
+            stack_overflow: action,
+            stack_underflow: action,
+            pc_out_of_range: action,
+            index_out_of_range: action,
+        });
+        chip9
+    }
+
+    fn run(chip9: &mut Chip9, ticks: usize) -> Result<(), Chip9Error> {
+        (0..ticks).try_for_each(|_| chip9.tick())
+    }
+
+    // V0 := 5, then a subroutine calling itself
+    const RECURSION: [u8; 4] = [0x60, 0x05, 0x22, 0x02];
+
+    // Jumps to the last word of 4K memory, where I := long 0x1234 leaves PC past the end
+    fn past_memory() -> Vec<u8> {
+        let mut rom = vec![0; 0xE02];
+        rom[..2].copy_from_slice(&[0x1F, 0xFE]);
+        rom[0xDFE..].copy_from_slice(&[0xF0, 0x00, 0x12, 0x34]);
+        rom
+    }
+
+    // V0-V2 := 11 22 33, I := 0xFFE, then stores them, the last one past the end of memory
+    const STORE_PAST_MEMORY: [u8; 10] = [0x60, 0x11, 0x61, 0x22, 0x62, 0x33, 0xAF, 0xFE, 0xF2, 0x55];
+
+    #[test]
+    fn stack_overflow_faults() {
+        let mut chip9 = machine(&RECURSION, FaultAction::Fault);
+        run(&mut chip9, 17).unwrap();
+
+        match chip9.tick() {
+            Err(Chip9Error::StackOverflow(context)) => {
+                assert_eq!((context.pc, context.opcode, context.stack_pointer), (0x202, 0x2202, 16));
+                assert_eq!(context.registers[0], 5);
+            }
+            other => panic!("expected a stack overflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stack_overflow_wraps() {
+        let mut chip9 = machine(&RECURSION, FaultAction::Wrap);
+        run(&mut chip9, 18).unwrap();
+
+        assert_eq!(chip9.cpu().stack(), [0x204]);
+        assert_eq!(chip9.cpu().program_counter(), 0x202);
+    }
+
+    #[test]
+    fn stack_overflow_is_ignored() {
+        let mut chip9 = machine(&RECURSION, FaultAction::Ignore);
+        run(&mut chip9, 18).unwrap();
+
+        assert_eq!(chip9.cpu().stack_pointer(), 16);
+        assert_eq!(chip9.cpu().program_counter(), 0x204);
+    }
+
+    #[test]
+    fn stack_underflow_faults() {
+        let mut chip9 = machine(&[0x00, 0xEE], FaultAction::Fault);
+
+        match chip9.tick() {
+            Err(Chip9Error::StackUnderflow(context)) => {
+                assert_eq!((context.pc, context.opcode, context.stack_pointer), (0x200, 0x00EE, 0));
+            }
+            other => panic!("expected a stack underflow, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stack_underflow_wraps() {
+        let mut chip9 = machine(&[0x00, 0xEE], FaultAction::Wrap);
+        chip9.tick().unwrap();
+
+        // Returns to the unused top entry of the stack
+        assert_eq!(chip9.cpu().stack_pointer(), 15);
+        assert_eq!(chip9.cpu().program_counter(), 0x000);
+    }
+
+    #[test]
+    fn stack_underflow_is_ignored() {
+        let mut chip9 = machine(&[0x00, 0xEE], FaultAction::Ignore);
+        chip9.tick().unwrap();
+
+        assert_eq!(chip9.cpu().stack_pointer(), 0);
+        assert_eq!(chip9.cpu().program_counter(), 0x202);
+    }
+
+    #[test]
+    fn pc_past_memory_faults() {
+        let mut chip9 = machine(&past_memory(), FaultAction::Fault);
+        run(&mut chip9, 2).unwrap();
+
+        match chip9.tick() {
+            Err(Chip9Error::PcOutOfRange(context)) => assert_eq!((context.pc, context.index), (0x1002, 0x1234)),
+            other => panic!("expected PC out of range, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn pc_past_memory_wraps() {
+        let mut chip9 = machine(&past_memory(), FaultAction::Wrap);
+        run(&mut chip9, 3).unwrap();
+
+        // Ran 9090 from the font at 0x002
+        assert_eq!(chip9.cpu().program_counter(), 0x004);
+    }
+
+    #[test]
+    fn pc_past_memory_is_ignored() {
+        let mut chip9 = machine(&past_memory(), FaultAction::Ignore);
+        run(&mut chip9, 3).unwrap();
+
+        assert_eq!(chip9.cpu().program_counter(), 0x1004);
+    }
+
+    #[test]
+    fn index_past_memory_faults() {
+        let mut chip9 = machine(&STORE_PAST_MEMORY, FaultAction::Fault);
+        run(&mut chip9, 4).unwrap();
+
+        match chip9.tick() {
+            Err(Chip9Error::IndexOutOfRange(context)) => {
+                assert_eq!((context.pc, context.opcode, context.index), (0x208, 0xF255, 0xFFE));
+                assert_eq!(context.registers[..3], [0x11, 0x22, 0x33]);
+            }
+            other => panic!("expected I out of range, got {:?}", other),
+        }
+        assert_eq!(chip9.cpu().read_memory(0xFFE, 2), [0, 0]);
+    }
+
+    #[test]
+    fn index_past_memory_wraps() {
+        let mut chip9 = machine(&STORE_PAST_MEMORY, FaultAction::Wrap);
+        run(&mut chip9, 5).unwrap();
+
+        assert_eq!(chip9.cpu().read_memory(0xFFE, 2), [0x11, 0x22]);
+        assert_eq!(chip9.cpu().read_memory(0x000, 1), [0x33]);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn index_past_memory_is_ignored() {
+        let mut chip9 = machine(&STORE_PAST_MEMORY, FaultAction::Ignore);
+        run(&mut chip9, 5).unwrap();
+
+        assert_eq!(chip9.cpu().read_memory(0xFFE, 2), [0, 0]);
+        assert_eq!(chip9.cpu().read_memory(0x000, 1), [0xF0]);
+        assert_eq!(chip9.cpu().program_counter(), 0x20A);
+    }
+}
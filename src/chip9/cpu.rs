@@ -1,56 +1,79 @@
 mod memory;
 mod opcode;
 mod registers;
+mod rng;
 mod timers;
 
-use std::{fs::File, sync::Arc};
+use std::{fs::File, io::Read};
 
 use crate::chip9::{
     display::Display,
+    FaultAction,
+    FaultContext,
+    FaultPolicy,
     Keyboard,
+    Quirks,
 };
 
+use crate::audio::AudioPattern;
+use crate::chip9::state::{StateReader, StateWriter};
 use crate::errors::Chip9Error;
-use memory::Memory;
-use opcode::{Addr, Nib, OpCode};
+use memory::{Memory, BIG_SPRITES_START};
+pub use opcode::{Addr, Nib, OpCode};
 use registers::Registers;
-use timers::{Timer, TimerClock};
+pub use rng::{RandomSource, SplitMix64};
+use timers::Timer;
 
 pub const PROGRAM_START: u16 = 0x200;
 const STACK_DEPTH: usize = 16;
 const SPRITE_SIZE: u16 = 5;
+const BIG_SPRITE_SIZE: u16 = 10;
+const NUM_FLAGS: usize = 16;
+const PATTERN_SIZE: usize = 16;
+const DEFAULT_PITCH: u8 = 64; // 4000Hz playback rate
+const MEMORY_LIMIT: u32 = 0x1000;
+const EXTENDED_MEMORY_LIMIT: u32 = 0x10000;
+
+enum Fault {
+    StackOverflow,
+    StackUnderflow,
+    PcOutOfRange,
+    IndexOutOfRange,
+    UnrecognizedOpcode(u16),
+}
 
 pub struct CPU {
     // Registers
     regs: Registers, // 16 general purpose 8-bit registers
-    idx: Addr, // 12-bit address register
-    dt: Arc<Timer>, // delay timer
-    st: Arc<Timer>, // sound timer
+    idx: Addr, // 16-bit address register
+    dt: Timer, // delay timer
+    st: Timer, // sound timer
     pc: Addr, // Program counter
-    sp: u8, // Stack pointer
-    stack: [Addr; STACK_DEPTH], // 16 12-bit stack fields
+    sp: u8, // Stack pointer, number of entries in use
+    stack: [Addr; STACK_DEPTH], // 16 16-bit stack fields
     mem: Memory,
-
-    _timer_clock: TimerClock
+    flags: [u8; NUM_FLAGS], // SCHIP persistent user flags (HP48 RPL)
+    pattern: Option<[u8; PATTERN_SIZE]>, // XO-CHIP audio pattern buffer, None until F002 loads one
+    pitch: u8, // XO-CHIP audio pattern playback rate
+    halted: bool,
+    quirks: Quirks,
+    vblank: bool, // set once per frame, consumed by Dxyn when waiting for the display
+    rng: Box<dyn RandomSource>,
+    faults: FaultPolicy,
 }
 
 impl CPU {
-    pub fn new() -> Self {
+    pub fn new(quirks: Quirks) -> Self {
         let regs = Registers::new();
         let idx = Addr::new();
-        let dt = Arc::new(Timer::new());
-        let st = Arc::new(Timer::new());
+        let dt = Timer::new();
+        let st = Timer::new();
         let pc = Addr::from(PROGRAM_START);
         let sp = 0x00;
         let stack = [Addr::new(); STACK_DEPTH];
 
         let mem = Memory::new();
 
-        let mut _timer_clock = TimerClock::new();
-        _timer_clock.register(dt.clone());
-        _timer_clock.register(st.clone());
-        _timer_clock.start();
-
         Self {
             regs,
             idx,
@@ -60,37 +83,126 @@ impl CPU {
             sp,
             stack,
             mem,
-            _timer_clock
+            flags: [0; NUM_FLAGS],
+            pattern: None,
+            pitch: DEFAULT_PITCH,
+            halted: false,
+            quirks,
+            vblank: false,
+            rng: Box::new(SplitMix64::new(rand::random())),
+            faults: FaultPolicy::default(),
         }
     }
 
-    pub fn load_program(&mut self, file: &File) -> Result<(), Box<dyn std::error::Error>> {
-        self.mem.load_from_file(file)
+    pub fn load_program(&mut self, mut file: &File) -> Result<(), Box<dyn std::error::Error>> {
+        let mut program = Vec::new();
+        file.read_to_end(&mut program)?;
+        self.load_rom(&program)
+    }
+
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Box<dyn std::error::Error>> {
+        self.mem.load(rom.iter().copied())
+    }
+
+    /// Replaces the source of CXKK random bytes
+    pub fn set_rng(&mut self, rng: Box<dyn RandomSource>) {
+        self.rng = rng;
+    }
+
+    pub fn set_fault_policy(&mut self, policy: FaultPolicy) {
+        self.faults = policy;
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Has the program exited through 00FD?
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Signals the start of a new 60Hz frame, counting the timers down
+    pub fn vblank(&mut self) {
+        self.vblank = true;
+        self.dt.tick();
+        self.st.tick();
     }
 
-    pub fn shutdown(&mut self) { // should be called only on started
-        self._timer_clock.shutdown();
+    fn memory_limit(&self) -> u32 {
+        if self.quirks.extended_memory { EXTENDED_MEMORY_LIMIT } else { MEMORY_LIMIT }
     }
 
-    fn fetch(&mut self) -> Result<OpCode, Chip9Error> {
+    // Returns None when the instruction is out of memory and should be skipped
+    fn fetch(&mut self) -> Result<Option<OpCode>, Fault> {
+        if self.pc.value() as u32 >= self.memory_limit() {
+            match self.faults.pc_out_of_range {
+                FaultAction::Fault => return Err(Fault::PcOutOfRange),
+                FaultAction::Wrap => self.pc = Addr::from((self.pc.value() as u32 % self.memory_limit()) as u16),
+                FaultAction::Ignore => {
+                    self.pc += 2;
+                    return Ok(None);
+                }
+            }
+        }
+
         let instruction = self.mem.get_instruction(self.pc);
-        self.pc += 2;
+        let operand = self.mem.get_instruction(self.pc + 2);
+        self.pc += OpCode::size(instruction);
 
-        OpCode::decode(instruction)
+        OpCode::decode(instruction, operand)
+            .map(Some)
+            .map_err(|_| Fault::UnrecognizedOpcode(instruction))
     }
 
     pub fn execute(&mut self, display: &mut Display, keyboard: &mut Keyboard) -> Result<(), Chip9Error> {
-        let opcode = self.fetch()?;
+        if self.halted {
+            return Ok(());
+        }
+
+        let pc = self.pc;
+        let (regs, idx, sp) = (self.registers(), self.idx, self.sp);
+        self.step(display, keyboard).map_err(|fault| {
+            let context = FaultContext {
+                pc: pc.value(),
+                opcode: self.mem.get_instruction(pc),
+                registers: regs,
+                index: idx.value(),
+                stack_pointer: sp,
+            };
+            match fault {
+                Fault::StackOverflow => Chip9Error::StackOverflow(context),
+                Fault::StackUnderflow => Chip9Error::StackUnderflow(context),
+                Fault::PcOutOfRange => Chip9Error::PcOutOfRange(context),
+                Fault::IndexOutOfRange => Chip9Error::IndexOutOfRange(context),
+                Fault::UnrecognizedOpcode(code) => Chip9Error::UnrecognizedOpcode(code),
+            }
+        })
+    }
+
+    fn step(&mut self, display: &mut Display, keyboard: &mut Keyboard) -> Result<(), Fault> {
+        let Some(opcode) = self.fetch()? else {
+            return Ok(());
+        };
 
         match opcode {
             OpCode::NoOp => (),
+            OpCode::ScrollDown(n) => self.scroll_down(n, display),
+            OpCode::ScrollUp(n) => self.scroll_up(n, display),
             OpCode::ClearScreen => self.cleared_screen(display),
-            OpCode::Return => self.return_subroutine(),
+            OpCode::Return => self.return_subroutine()?,
+            OpCode::ScrollRight => self.scroll_right(display),
+            OpCode::ScrollLeft => self.scroll_left(display),
+            OpCode::Exit => self.exit(),
+            OpCode::LowRes => self.set_resolution(false, display),
+            OpCode::HighRes => self.set_resolution(true, display),
             OpCode::Jump(addr) => self.jump_addr(addr),
-            OpCode::Call(addr) => self.call_addr(addr),
+            OpCode::Call(addr) => self.call_addr(addr)?,
             OpCode::SkipEqualByte(x, byte) => self.skip_eq_byte(x, byte),
             OpCode::SkipNotEqualByte(x, byte) => self.skip_neq_byte(x, byte),
             OpCode::SkipEqualReg(x, y) => self.skip_eq_reg(x, y),
+            OpCode::StoreRange(x, y) => self.store_range(x, y)?,
+            OpCode::LoadRange(x, y) => self.load_range(x, y)?,
             OpCode::LoadByte(x, byte) => self.load_byte(x, byte),
             OpCode::AddByte(x, byte) => self.add_byte(x, byte),
             OpCode::LoadReg(x, y) => self.load_reg(x, y),
@@ -99,25 +211,32 @@ impl CPU {
             OpCode::XorReg(x, y) => self.xor_reg(x, y),
             OpCode::AddReg(x, y) => self.add_reg(x, y),
             OpCode::SubReg(x, y) => self.sub_reg(x, y),
-            OpCode::ShiftRight(x, _) => self.shr_reg(x),
+            OpCode::ShiftRight(x, y) => self.shr_reg(x, y),
             OpCode::SubNot(x, y) => self.subn_reg(x, y),
-            OpCode::ShiftLeft(x, _) => self.shl_reg(x),
+            OpCode::ShiftLeft(x, y) => self.shl_reg(x, y),
             OpCode::SkipNotEqualReg(x, y) => self.skip_neq_reg(x, y),
             OpCode::LoadIndex(addr) => self.load_idx(addr),
             OpCode::JumpV0(addr) => self.jump_v0(addr),
             OpCode::RandomByte(x, byte) => self.random_byte(x, byte),
-            OpCode::Draw(x, y, n) => self.draw(x, y, n, display),
+            OpCode::Draw(x, y, n) => self.draw(x, y, n, display)?,
             OpCode::SkipKeyPressed(x) => self.skip_key_pressed(x, keyboard),
             OpCode::SkipKeyNotPressed(x) => self.skip_key_not_pressed(x, keyboard),
+            OpCode::LoadLongIndex(addr) => self.load_idx(addr),
+            OpCode::SelectPlanes(n) => self.select_planes(n, display),
+            OpCode::LoadAudio => self.load_audio()?,
             OpCode::LoadDelay(x) => self.load_delay(x),
             OpCode::WaitKey(x) => self.wait_key(x, keyboard),
             OpCode::SetDelay(x) => self.set_delay(x),
             OpCode::SetSound(x) => self.set_sound(x),
             OpCode::AddToIndex(x) => self.add_idx(x),
             OpCode::LoadFont(x) => self.load_sprite(x),
-            OpCode::LoadBCD(x) => self.load_bcd(x),
-            OpCode::StoreRegs(x) => self.store_regs(x),
-            OpCode::LoadRegs(x) => self.load_regs(x),
+            OpCode::LoadBigFont(x) => self.load_big_sprite(x),
+            OpCode::LoadBCD(x) => self.load_bcd(x)?,
+            OpCode::SetPitch(x) => self.set_pitch(x),
+            OpCode::StoreRegs(x) => self.store_regs(x)?,
+            OpCode::LoadRegs(x) => self.load_regs(x)?,
+            OpCode::StoreFlags(x) => self.store_flags(x),
+            OpCode::LoadFlags(x) => self.load_flags(x),
         }
 
         Ok(())
@@ -127,43 +246,227 @@ impl CPU {
         self.st.get()
     }
 
-    fn return_subroutine(&mut self) {
-        self.pc = self.stack[self.sp as usize];
+    /// General purpose registers V0-VF
+    pub fn registers(&self) -> [u8; 16] {
+        std::array::from_fn(|i| self.regs[Nib::from(i as u8)])
+    }
+
+    pub fn index(&self) -> u16 {
+        self.idx.value()
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.pc.value()
+    }
+
+    pub fn stack_pointer(&self) -> u8 {
+        self.sp
+    }
+
+    /// Return addresses of the active calls, outermost first
+    pub fn stack(&self) -> Vec<u16> {
+        self.stack[..self.sp as usize].iter().map(Addr::value).collect()
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.dt.get()
+    }
+
+    /// Reads `len` bytes starting at `addr`, wrapping around the end of memory
+    pub fn read_memory(&self, addr: u16, len: usize) -> Vec<u8> {
+        (0..len).map(|offset| self.mem.read_byte(Addr::from(addr) + offset as u16)).collect()
+    }
+
+    /// Decodes the instruction at `addr` without executing it
+    pub fn decode_at(&self, addr: u16) -> Result<OpCode, Chip9Error> {
+        let addr = Addr::from(addr);
+        OpCode::decode(self.mem.get_instruction(addr), self.mem.get_instruction(addr + 2))
+    }
+
+    /// XO-CHIP audio pattern played by the sound timer, None until the program loads one
+    pub fn audio_pattern(&self) -> Option<AudioPattern> {
+        let rate = 4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0);
+        self.pattern.map(|samples| AudioPattern { samples, rate })
+    }
+
+    pub(crate) fn save_state(&self, writer: &mut StateWriter) {
+        self.regs.save_state(writer);
+        writer.write_u16(self.idx.value());
+        writer.write_u16(self.pc.value());
+        writer.write_u8(self.sp);
+        for addr in &self.stack {
+            writer.write_u16(addr.value());
+        }
+        writer.write_u8(self.dt.get());
+        writer.write_u8(self.st.get());
+        writer.write_bytes(&self.flags);
+        writer.write_bool(self.pattern.is_some());
+        writer.write_bytes(&self.pattern.unwrap_or_default());
+        writer.write_u8(self.pitch);
+        writer.write_bool(self.halted);
+        writer.write_bool(self.vblank);
+        writer.write_u64(self.rng.state());
+        self.mem.save_state(writer);
+    }
+
+    pub(crate) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Chip9Error> {
+        let mut regs = Registers::new();
+        regs.load_state(reader)?;
+        let idx = reader.read_u16()?;
+        let pc = reader.read_u16()?;
+        let sp = reader.read_u8()?;
+        // Checked before anything is restored, the rest of the state can't be invalid
+        if sp as usize > STACK_DEPTH {
+            return Err(Chip9Error::InvalidState(format!("stack pointer {} is past the {}-entry stack", sp, STACK_DEPTH)));
+        }
+
+        self.regs = regs;
+        self.idx = Addr::from(idx);
+        self.pc = Addr::from(pc);
+        self.sp = sp;
+        for addr in self.stack.iter_mut() {
+            *addr = Addr::from(reader.read_u16()?);
+        }
+        self.dt.load(reader.read_u8()?);
+        self.st.load(reader.read_u8()?);
+        self.flags.copy_from_slice(reader.read_bytes(NUM_FLAGS)?);
+        let has_pattern = reader.read_bool()?;
+        let pattern = reader.read_bytes(PATTERN_SIZE)?.try_into().unwrap();
+        self.pattern = has_pattern.then_some(pattern);
+        self.pitch = reader.read_u8()?;
+        self.halted = reader.read_bool()?;
+        self.vblank = reader.read_bool()?;
+        self.rng.set_state(reader.read_u64()?);
+        self.mem.load_state(reader)
+    }
+
+    // Skips the next instruction, which may be 4 bytes long on XO-CHIP
+    fn skip(&mut self) {
+        let next = self.mem.get_instruction(self.pc);
+        self.pc += OpCode::size(next);
+    }
+
+    fn return_subroutine(&mut self) -> Result<(), Fault> {
+        if self.sp == 0 {
+            match self.faults.stack_underflow {
+                FaultAction::Fault => return Err(Fault::StackUnderflow),
+                FaultAction::Wrap => self.sp = STACK_DEPTH as u8,
+                FaultAction::Ignore => return Ok(()),
+            }
+        }
         self.sp -= 1;
+        self.pc = self.stack[self.sp as usize];
+        Ok(())
     }
 
     fn cleared_screen(&mut self, display: &mut Display) {
         display.clear();
     }
 
+    // 00Cn, 00Dn, 00FB, 00FC - SCHIP/XO-CHIP scrolling
+    fn scroll_down(&mut self, rows: Nib, display: &mut Display) {
+        display.scroll_down(rows.value() as usize);
+    }
+
+    fn scroll_up(&mut self, rows: Nib, display: &mut Display) {
+        display.scroll_up(rows.value() as usize);
+    }
+
+    fn scroll_right(&mut self, display: &mut Display) {
+        display.scroll_right();
+    }
+
+    fn scroll_left(&mut self, display: &mut Display) {
+        display.scroll_left();
+    }
+
+    fn exit(&mut self) {
+        self.halted = true;
+    }
+
+    fn set_resolution(&mut self, hires: bool, display: &mut Display) {
+        display.set_hires(hires);
+    }
+
     fn jump_addr(&mut self, addr: Addr) {
         self.pc = addr;
     }
 
-    fn call_addr(&mut self, addr: Addr) {
-        self.sp += 1;
+    fn call_addr(&mut self, addr: Addr) -> Result<(), Fault> {
+        if self.sp as usize == STACK_DEPTH {
+            match self.faults.stack_overflow {
+                FaultAction::Fault => return Err(Fault::StackOverflow),
+                FaultAction::Wrap => self.sp = 0,
+                FaultAction::Ignore => return Ok(()),
+            }
+        }
         self.stack[self.sp as usize] = self.pc;
+        self.sp += 1;
         self.pc = addr;
+        Ok(())
     }
 
     fn skip_eq_byte(&mut self, vx: Nib, byte: u8) {
         if self.regs[vx] == byte {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     fn skip_neq_byte(&mut self, vx: Nib, byte: u8) {
         if self.regs[vx] != byte {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     fn skip_eq_reg(&mut self, vx: Nib, vy: Nib) {
         if self.regs[vx] == self.regs[vy] {
-            self.pc += 2;
+            self.skip();
+        }
+    }
+
+    // 5xy2/5xy3 - registers are transferred in reverse when x > y, I is left untouched
+    fn store_range(&mut self, vx: Nib, vy: Nib) -> Result<(), Fault> {
+        if !self.check_index(vx.value().abs_diff(vy.value()) as u16 + 1)? {
+            return Ok(());
+        }
+        for (i, reg) in Self::reg_range(vx, vy).enumerate() {
+            self.mem.write_byte(self.index_addr(i as u16), self.regs[reg]);
+        }
+        Ok(())
+    }
+
+    fn load_range(&mut self, vx: Nib, vy: Nib) -> Result<(), Fault> {
+        if !self.check_index(vx.value().abs_diff(vy.value()) as u16 + 1)? {
+            return Ok(());
+        }
+        for (i, reg) in Self::reg_range(vx, vy).enumerate() {
+            self.regs[reg] = self.mem.read_byte(self.index_addr(i as u16));
+        }
+        Ok(())
+    }
+
+    // Checks that `len` bytes from I fit in memory, returns false when the access should be skipped
+    fn check_index(&self, len: u16) -> Result<bool, Fault> {
+        if self.idx.value() as u32 + len as u32 <= self.memory_limit() {
+            return Ok(true);
+        }
+        match self.faults.index_out_of_range {
+            FaultAction::Fault => Err(Fault::IndexOutOfRange),
+            FaultAction::Wrap => Ok(true),
+            FaultAction::Ignore => Ok(false),
         }
     }
 
+    // Address `offset` bytes past I, wrapping around the end of memory
+    fn index_addr(&self, offset: u16) -> Addr {
+        Addr::from(((self.idx.value() as u32 + offset as u32) % self.memory_limit()) as u16)
+    }
+
+    fn reg_range(vx: Nib, vy: Nib) -> impl Iterator<Item = Nib> {
+        let (x, y) = (vx.value(), vy.value());
+        (0..=x.abs_diff(y)).map(move |i| Nib::from(if x <= y { x + i } else { x - i }))
+    }
+
     fn load_byte(&mut self, vx: Nib, byte: u8) {
         self.regs[vx] = byte;
     }
@@ -178,49 +481,59 @@ impl CPU {
 
     fn or_reg(&mut self, vx: Nib, vy: Nib) {
         self.regs[vx] |= self.regs[vy];
+        self.reset_flag();
     }
 
     fn and_reg(&mut self, vx: Nib, vy: Nib) {
         self.regs[vx] &= self.regs[vy];
+        self.reset_flag();
     }
 
     fn xor_reg(&mut self, vx: Nib, vy: Nib) {
         self.regs[vx] ^= self.regs[vy];
+        self.reset_flag();
     }
 
+    fn reset_flag(&mut self) {
+        if self.quirks.vf_reset {
+            self.regs.set_flag(0);
+        }
+    }
+
+    // The flag is written after the result, so VF holds the flag when it's also the target
     fn add_reg(&mut self, vx: Nib, vy: Nib) {
         let (sum, carry) = self.regs[vx].overflowing_add(self.regs[vy]);
-        self.regs.set_flag(carry as u8);
         self.regs[vx] = sum;
+        self.regs.set_flag(carry as u8);
     }
 
     fn sub_reg(&mut self, vx: Nib, vy: Nib) {
         let (diff, borrow) = self.regs[vx].overflowing_sub(self.regs[vy]);
-        self.regs.set_flag((!borrow) as u8);
         self.regs[vx] = diff;
+        self.regs.set_flag((!borrow) as u8);
     }
 
-    fn shr_reg(&mut self, vx: Nib) {
-        let underflow = self.regs[vx] & 1;
-        self.regs.set_flag(underflow);
-        self.regs[vx] >>= 1;
+    fn shr_reg(&mut self, vx: Nib, vy: Nib) {
+        let src = if self.quirks.shift_uses_vy { self.regs[vy] } else { self.regs[vx] };
+        self.regs[vx] = src >> 1;
+        self.regs.set_flag(src & 1);
     }
 
     fn subn_reg(&mut self, vx: Nib, vy: Nib) {
         let (diff, borrow) = self.regs[vy].overflowing_sub(self.regs[vx]);
-        self.regs.set_flag((!borrow) as u8);
         self.regs[vx] = diff;
-}
+        self.regs.set_flag((!borrow) as u8);
+    }
 
-    fn shl_reg(&mut self, vx: Nib) {
-        let overflow = self.regs[vx] >> 7;
-        self.regs.set_flag(overflow);
-        self.regs[vx] <<= 1;
+    fn shl_reg(&mut self, vx: Nib, vy: Nib) {
+        let src = if self.quirks.shift_uses_vy { self.regs[vy] } else { self.regs[vx] };
+        self.regs[vx] = src << 1;
+        self.regs.set_flag(src >> 7);
     }
 
     fn skip_neq_reg(&mut self, vx: Nib, vy: Nib) {
         if self.regs[vx] != self.regs[vy] {
-            self.pc += 2;
+            self.skip();
         }
     }
 
@@ -229,38 +542,94 @@ impl CPU {
     }
 
     fn jump_v0(&mut self, addr: Addr) {
-        self.pc = addr + self.regs.v0().into();
+        let offset = if self.quirks.jump_uses_vx {
+            self.regs[Nib::from((addr.value() >> 8) as u8)]
+        } else {
+            self.regs.v0()
+        };
+        self.pc = addr + offset.into();
     }
 
     fn random_byte(&mut self, vx: Nib, byte: u8) {
-        let rnd: u8 = rand::random();
-        self.regs[vx] = byte & rnd;
+        self.regs[vx] = byte & self.rng.next_byte();
     }
 
-    fn draw(&mut self, vx: Nib, vy: Nib, height: Nib, display: &mut Display) {
-        // Read sprite from memory
-        let sprite = (0..height.value())
-            .map(|offset| self.mem.read_byte(self.idx + offset as u16));
+    fn draw(&mut self, vx: Nib, vy: Nib, height: Nib, display: &mut Display) -> Result<(), Fault> {
+        // Wait for the vertical blank, like the VIP interrupt routine did
+        if self.quirks.display_wait {
+            if !self.vblank {
+                self.pc -= 2;
+                return Ok(());
+            }
+            self.vblank = false;
+        }
+
+        // Dxy0 draws a 16x16 sprite
+        let (width, rows) = match height.value() {
+            0 => (16, 16),
+            n => (8, n as u16),
+        };
+        let bytes_per_row = width as u16 / 8;
+        let sprite_len = rows * bytes_per_row;
+        if !self.check_index(sprite_len * display.selected_planes().count() as u16)? {
+            return Ok(());
+        }
 
         let x = self.regs[vx] as usize;
         let y = self.regs[vy] as usize;
 
-        // Draw sprite and set collision flag
-        let collision = display.draw(x, y, sprite);
-        self.regs.set_flag(collision as u8);
+        // Each selected plane takes the next sprite from memory
+        let mut collisions = 0;
+        for (i, plane) in display.selected_planes().enumerate() {
+            let start = i as u16 * sprite_len;
+            let sprite = (0..rows).map(|row| {
+                let offset = start + row * bytes_per_row;
+                if bytes_per_row == 2 {
+                    u16::from_be_bytes([self.mem.read_byte(self.index_addr(offset)), self.mem.read_byte(self.index_addr(offset + 1))])
+                } else {
+                    self.mem.read_byte(self.index_addr(offset)) as u16
+                }
+            });
+            collisions = collisions.max(display.draw(plane, x, y, sprite, width, self.quirks.clip_sprites));
+        }
+
+        // Set collision flag
+        if self.quirks.row_collision && display.is_hires() {
+            // Rows past the bottom count only when they're clipped, wrapped ones were drawn
+            let clipped = match self.quirks.clip_sprites {
+                true => (y % display.height() + rows as usize).saturating_sub(display.height()),
+                false => 0,
+            };
+            self.regs.set_flag(collisions + clipped as u8);
+        } else {
+            self.regs.set_flag((collisions > 0) as u8);
+        }
+        Ok(())
     }
 
     // Ennn - Keyboard operations
     fn skip_key_pressed(&mut self, vx: Nib, keyboard: &mut Keyboard) {
         if keyboard.is_key_pressed(self.regs[vx]) {
-            self.pc += 2;
+            self.skip();
         }
     }
 
     fn skip_key_not_pressed(&mut self, vx: Nib, keyboard: &mut Keyboard) {
         if !keyboard.is_key_pressed(self.regs[vx]) {
-            self.pc += 2;
+            self.skip();
+        }
+    }
+
+    fn select_planes(&mut self, planes: Nib, display: &mut Display) {
+        display.select_planes(planes.value());
+    }
+
+    fn load_audio(&mut self) -> Result<(), Fault> {
+        if !self.check_index(PATTERN_SIZE as u16)? {
+            return Ok(());
         }
+        self.pattern = Some(std::array::from_fn(|i| self.mem.read_byte(self.index_addr(i as u16))));
+        Ok(())
     }
 
     fn load_delay(&mut self, vx: Nib) {
@@ -291,25 +660,59 @@ impl CPU {
         self.idx = Addr::from( SPRITE_SIZE * self.regs[vx] as u16);
     }
 
-    fn load_bcd(&mut self, vx: Nib) {
-        self.mem.write_byte(self.idx, self.regs[vx] / 100);
-        self.mem.write_byte(self.idx + 1, (self.regs[vx] % 100) / 10);
-        self.mem.write_byte(self.idx + 2, self.regs[vx] % 10);
+    fn load_big_sprite(&mut self, vx: Nib) {
+        self.idx = Addr::from(BIG_SPRITES_START + BIG_SPRITE_SIZE * (self.regs[vx] & 0xF) as u16);
+    }
+
+    fn load_bcd(&mut self, vx: Nib) -> Result<(), Fault> {
+        if !self.check_index(3)? {
+            return Ok(());
+        }
+        self.mem.write_byte(self.index_addr(0), self.regs[vx] / 100);
+        self.mem.write_byte(self.index_addr(1), (self.regs[vx] % 100) / 10);
+        self.mem.write_byte(self.index_addr(2), self.regs[vx] % 10);
+        Ok(())
+    }
+
+    fn set_pitch(&mut self, vx: Nib) {
+        self.pitch = self.regs[vx];
     }
 
-    fn store_regs(&mut self, vx: Nib) {
+    fn store_regs(&mut self, vx: Nib) -> Result<(), Fault> {
+        if !self.check_index(vx.value() as u16 + 1)? {
+            return Ok(());
+        }
         for i in 0..=vx.value() {
             let nib = Nib::from(i);
-            self.mem.write_byte(self.idx + i as u16, self.regs[nib]);
+            self.mem.write_byte(self.index_addr(i as u16), self.regs[nib]);
         }
-        self.idx += vx.value() as u16 + 1;
+        self.idx += self.quirks.load_store_increment.amount(vx.value());
+        Ok(())
     }
 
-    fn load_regs(&mut self, vx: Nib) {
+    fn load_regs(&mut self, vx: Nib) -> Result<(), Fault> {
+        if !self.check_index(vx.value() as u16 + 1)? {
+            return Ok(());
+        }
         for i in 0..=vx.value() {
             let nib = Nib::from(i);
-            self.regs[nib] = self.mem.read_byte(self.idx + i as u16);
+            self.regs[nib] = self.mem.read_byte(self.index_addr(i as u16));
         }
-        self.idx += vx.value() as u16 + 1;
+        self.idx += self.quirks.load_store_increment.amount(vx.value());
+        Ok(())
     }
-}
\ No newline at end of file
+
+    fn store_flags(&mut self, vx: Nib) {
+        for i in 0..=vx.value() {
+            let nib = Nib::from(i);
+            self.flags[i as usize] = self.regs[nib];
+        }
+    }
+
+    fn load_flags(&mut self, vx: Nib) {
+        for i in 0..=vx.value() {
+            let nib = Nib::from(i);
+            self.regs[nib] = self.flags[i as usize];
+        }
+    }
+}
@@ -1,12 +1,13 @@
 use std::error::Error;
-use std::io::{BufReader, Read};
-use std::fs::File;
 
 use super::{Addr, PROGRAM_START};
+use crate::chip9::state::{StateReader, StateWriter};
 use crate::errors::Chip9Error;
 
-const MEMORY_SIZE: usize = 1024 * 4;
+const MEMORY_SIZE: usize = 1024 * 64;
 const SPRITES_MEMORY: usize = 80;
+const BIG_SPRITES_MEMORY: usize = 160;
+pub const BIG_SPRITES_START: u16 = SPRITES_MEMORY as u16;
 
 const SPRITES: [u8; SPRITES_MEMORY] = [
             0xf0, 0x90, 0x90, 0x90, 0xf0, // "0"
@@ -27,6 +28,25 @@ const SPRITES: [u8; SPRITES_MEMORY] = [
             0xf0, 0x80, 0xf0, 0x80, 0x80  // "F"
 ];
 
+const BIG_SPRITES: [u8; BIG_SPRITES_MEMORY] = [
+            0x3c, 0x7e, 0xe7, 0xc3, 0xc3, 0xc3, 0xc3, 0xe7, 0x7e, 0x3c, // "0"
+            0x18, 0x38, 0x58, 0x18, 0x18, 0x18, 0x18, 0x18, 0x18, 0x3c, // "1"
+            0x3e, 0x7f, 0xc3, 0x06, 0x0c, 0x18, 0x30, 0x60, 0xff, 0xff, // "2"
+            0x3c, 0x7e, 0xc3, 0x03, 0x0e, 0x0e, 0x03, 0xc3, 0x7e, 0x3c, // "3"
+            0x06, 0x0e, 0x1e, 0x36, 0x66, 0xc6, 0xff, 0xff, 0x06, 0x06, // "4"
+            0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfe, 0x03, 0xc3, 0x7e, 0x3c, // "5"
+            0x3e, 0x7c, 0xe0, 0xc0, 0xfc, 0xfe, 0xc3, 0xc3, 0x7e, 0x3c, // "6"
+            0xff, 0xff, 0x03, 0x06, 0x0c, 0x18, 0x30, 0x60, 0x60, 0x60, // "7"
+            0x3c, 0x7e, 0xc3, 0xc3, 0x7e, 0x7e, 0xc3, 0xc3, 0x7e, 0x3c, // "8"
+            0x3c, 0x7e, 0xc3, 0xc3, 0x7f, 0x3f, 0x03, 0x03, 0x3e, 0x7c, // "9"
+            0x18, 0x3c, 0x66, 0xc3, 0xc3, 0xff, 0xff, 0xc3, 0xc3, 0xc3, // "A"
+            0xfc, 0xfe, 0xc3, 0xc3, 0xfe, 0xfe, 0xc3, 0xc3, 0xfe, 0xfc, // "B"
+            0x3c, 0x7e, 0xe3, 0xc0, 0xc0, 0xc0, 0xc0, 0xe3, 0x7e, 0x3c, // "C"
+            0xfc, 0xfe, 0xc7, 0xc3, 0xc3, 0xc3, 0xc3, 0xc7, 0xfe, 0xfc, // "D"
+            0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfc, 0xc0, 0xc0, 0xff, 0xff, // "E"
+            0xff, 0xff, 0xc0, 0xc0, 0xfc, 0xfc, 0xc0, 0xc0, 0xc0, 0xc0  // "F"
+];
+
 pub struct Memory {
     memory: [u8; MEMORY_SIZE]
 }
@@ -36,9 +56,11 @@ impl Memory {
         let mut memory = [0; MEMORY_SIZE];
 
         // sprites - 0x00 to 0x4F
-        for (i, &byte) in SPRITES.iter().enumerate() {
-            memory[i] = byte;
-        }
+        memory[..SPRITES_MEMORY].copy_from_slice(&SPRITES);
+
+        // big sprites - 0x50 to 0xEF
+        let big_sprites = BIG_SPRITES_START as usize;
+        memory[big_sprites..big_sprites + BIG_SPRITES_MEMORY].copy_from_slice(&BIG_SPRITES);
 
         Memory { memory }
     }
@@ -59,27 +81,22 @@ impl Memory {
         ((high_byte as u16) << 8) | low_byte as u16
     }
 
-    pub fn load(&mut self, program: impl Iterator<Item = u8>) -> Result<(), Box<dyn Error>> {
-        for (i, byte) in program.enumerate() {
-            let idx = PROGRAM_START as usize + i;
-            if idx >= MEMORY_SIZE {
-                return Err(Box::new(Chip9Error::TooManyLines(i, MEMORY_SIZE)));
-            }
-            self.memory[idx] = byte;
-        }
-        Ok(())
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.memory);
     }
 
-    // todo: remove it, lets use load only
-    pub fn load_from_file(&mut self, file: &File) -> Result<(), Box<dyn Error>> {
-        let f = BufReader::new(file);
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Chip9Error> {
+        self.memory.copy_from_slice(reader.read_bytes(MEMORY_SIZE)?);
+        Ok(())
+    }
 
-        for (i, byte) in f.bytes().enumerate() {
+    pub fn load(&mut self, program: impl Iterator<Item = u8>) -> Result<(), Box<dyn Error>> {
+        for (i, byte) in program.enumerate() {
             let idx = PROGRAM_START as usize + i;
             if idx >= MEMORY_SIZE {
                 return Err(Box::new(Chip9Error::TooManyLines(i, MEMORY_SIZE)));
             }
-            self.memory[idx] = byte?;
+            self.memory[idx] = byte;
         }
         Ok(())
     }
@@ -1,10 +1,12 @@
+use std::fmt;
 use std::ops::{Add, AddAssign, SubAssign};
 use crate::errors::Chip9Error;
 
-const ADDR_MASK: u16 = 0x0FFF;
+const ADDR_MASK: u16 = 0xFFFF; // XO-CHIP addresses the whole 64K memory
 const NIB_MASK: u8 = 0x0F;
+const LONG_INDEX: u16 = 0xF000;
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Addr(u16);
 
 impl Addr {
@@ -25,19 +27,25 @@ impl Add<u16> for Addr {
     type Output = Addr;
 
     fn add(self, rhs: u16) -> Self::Output {
-        Self::from(self.0.wrapping_add(rhs as u16))
+        Self::from(self.0.wrapping_add(rhs))
     }
 }
 
 impl AddAssign<u16> for Addr {
     fn add_assign(&mut self, rhs: u16) {
-        self.0 = self.0 + rhs
+        *self = *self + rhs;
     }
 }
 
 impl SubAssign<u16> for Addr {
     fn sub_assign(&mut self, rhs: u16) {
-        self.0 = self.0 - rhs;
+        *self = Self::from(self.0.wrapping_sub(rhs));
+    }
+}
+
+impl fmt::Display for Addr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "0x{:03X}", self.0)
     }
 }
 
@@ -54,15 +62,25 @@ impl Nib {
     }
 }
 
+#[derive(Clone, Copy)]
 pub enum OpCode {
     NoOp,                     // 0000 - NOP
+    ScrollDown(Nib),          // 00Cn - SCD nibble (SCHIP)
+    ScrollUp(Nib),            // 00Dn - SCU nibble (XO-CHIP)
     ClearScreen,              // 00E0 - CLS
     Return,                   // 00EE - RET
+    ScrollRight,              // 00FB - SCR (SCHIP)
+    ScrollLeft,               // 00FC - SCL (SCHIP)
+    Exit,                     // 00FD - EXIT (SCHIP)
+    LowRes,                   // 00FE - LOW (SCHIP)
+    HighRes,                  // 00FF - HIGH (SCHIP)
     Jump(Addr),               // 1aaa - JP addr
     Call(Addr),               // 2aaa - CALL addr
     SkipEqualByte(Nib, u8),   // 3xkk - SE Vx, byte
     SkipNotEqualByte(Nib, u8),// 4xkk - SNE Vx, byte
     SkipEqualReg(Nib, Nib),   // 5xy0 - SE Vx, Vy
+    StoreRange(Nib, Nib),     // 5xy2 - LD [I], Vx-Vy (XO-CHIP)
+    LoadRange(Nib, Nib),      // 5xy3 - LD Vx-Vy, [I] (XO-CHIP)
     LoadByte(Nib, u8),        // 6xkk - LD Vx, byte
     AddByte(Nib, u8),         // 7xkk - ADD Vx, byte
     LoadReg(Nib, Nib),        // 8xy0 - LD Vx, Vy
@@ -71,25 +89,32 @@ pub enum OpCode {
     XorReg(Nib, Nib),         // 8xy3 - XOR Vx, Vy
     AddReg(Nib, Nib),         // 8xy4 - ADD Vx, Vy
     SubReg(Nib, Nib),         // 8xy5 - SUB Vx, Vy
-    ShiftRight(Nib, Nib),     // 8xy6 - SHR Vx {, Vy} - Vy is not used
+    ShiftRight(Nib, Nib),     // 8xy6 - SHR Vx {, Vy} - Vy is used with the shift quirk
     SubNot(Nib, Nib),         // 8xy7 - SUBN Vx, Vy
-    ShiftLeft(Nib, Nib),      // 8xyE - SHL Vx {, Vy} - Vy is not used
+    ShiftLeft(Nib, Nib),      // 8xyE - SHL Vx {, Vy} - Vy is used with the shift quirk
     SkipNotEqualReg(Nib, Nib),// 9xy0 - SNE Vx, Vy
     LoadIndex(Addr),          // Aaaa - LD I, addr
     JumpV0(Addr),             // Baaa - JP V0, addr
     RandomByte(Nib, u8),      // Cxkk - RND Vx, byte
-    Draw(Nib, Nib, Nib),      // Dxyn - DRW Vx, Vy, nibble
+    Draw(Nib, Nib, Nib),      // Dxyn - DRW Vx, Vy, nibble - 16x16 sprite for n = 0 (SCHIP)
     SkipKeyPressed(Nib),      // Ex9E - SKP Vx
     SkipKeyNotPressed(Nib),   // ExA1 - SKNP Vx
+    LoadLongIndex(Addr),      // F000 nnnn - LD I, long addr (XO-CHIP)
+    SelectPlanes(Nib),        // Fn01 - PLANE n (XO-CHIP)
+    LoadAudio,                // F002 - AUDIO (XO-CHIP)
     LoadDelay(Nib),           // Fx07 - LD Vx, DT
     WaitKey(Nib),             // Fx0A - LD Vx, K
     SetDelay(Nib),            // Fx15 - LD DT, Vx
     SetSound(Nib),            // Fx18 - LD ST, Vx
     AddToIndex(Nib),          // Fx1E - ADD I, Vx
     LoadFont(Nib),            // Fx29 - LD F, Vx
+    LoadBigFont(Nib),         // Fx30 - LD HF, Vx (SCHIP)
     LoadBCD(Nib),             // Fx33 - LD B, Vx
+    SetPitch(Nib),            // Fx3A - PITCH Vx (XO-CHIP)
     StoreRegs(Nib),           // Fx55 - LD [I], Vx
     LoadRegs(Nib),            // Fx65 - LD Vx, [I]
+    StoreFlags(Nib),          // Fx75 - LD R, Vx (SCHIP)
+    LoadFlags(Nib),           // Fx85 - LD Vx, R (SCHIP)
 }
 
 struct Deconstructed {
@@ -115,11 +140,13 @@ struct Deconstructed {
         x, y - 4-bit register index
         n - 4-bit numeric value
         kk - 8-bit immediate value
+
+    XO-CHIP's F000 is followed by a second 16-bit word holding a full 16-bit address.
 */
 impl Deconstructed {
     fn new(code: u16) -> Self {
         let group = Nib::from((code >> 12) as u8);
-        let addr = Addr::from(code & 0xFFF);
+        let addr = Addr::from(code & 0x0FFF);
         let x = Nib::from((code >> 8) as u8);
         let y = Nib::from((code >> 4) as u8);
         let n = Nib::from(code as u8);
@@ -137,7 +164,13 @@ impl Deconstructed {
 }
 
 impl OpCode {
-    pub fn decode(code: u16) -> Result<Self, Chip9Error> {
+    /// Size in bytes of the instruction starting with `code`
+    pub fn size(code: u16) -> u16 {
+        if code == LONG_INDEX { 4 } else { 2 }
+    }
+
+    /// Decodes `code`, `operand` is the word following it and is only used by 4-byte instructions
+    pub fn decode(code: u16, operand: u16) -> Result<Self, Chip9Error> {
         use OpCode::*;
 
         let dec = Deconstructed::new(code);
@@ -147,6 +180,13 @@ impl OpCode {
                 0x0E0 => Ok(ClearScreen),
                 0x0EE => Ok(Return),
                 0x000 => Ok(NoOp),
+                0x0C0..=0x0CF => Ok(ScrollDown(dec.n)),
+                0x0D0..=0x0DF => Ok(ScrollUp(dec.n)),
+                0x0FB => Ok(ScrollRight),
+                0x0FC => Ok(ScrollLeft),
+                0x0FD => Ok(Exit),
+                0x0FE => Ok(LowRes),
+                0x0FF => Ok(HighRes),
                 _ => Err(Chip9Error::UnrecognizedOpcode(dec.code)),
             },
 
@@ -158,7 +198,12 @@ impl OpCode {
 
             0x4 => Ok(SkipNotEqualByte(dec.x, dec.kk)),
 
-            0x5 if dec.n.value() == 0x0 => Ok(SkipEqualReg(dec.x, dec.y)),
+            0x5 => match dec.n.value() {
+                0x0 => Ok(SkipEqualReg(dec.x, dec.y)),
+                0x2 => Ok(StoreRange(dec.x, dec.y)),
+                0x3 => Ok(LoadRange(dec.x, dec.y)),
+                _ => Err(Chip9Error::UnrecognizedOpcode(dec.code)),
+            },
 
             0x6 => Ok(LoadByte(dec.x, dec.kk)),
 
@@ -193,20 +238,111 @@ impl OpCode {
                 _ => Err(Chip9Error::UnrecognizedOpcode(dec.code)),
             },
 
+            0xF if dec.code == LONG_INDEX => Ok(LoadLongIndex(Addr::from(operand))),
+
             0xF => match dec.kk {
+                0x01 => Ok(SelectPlanes(dec.x)),
+                0x02 if dec.x.value() == 0x0 => Ok(LoadAudio),
                 0x07 => Ok(LoadDelay(dec.x)),
                 0x0A => Ok(WaitKey(dec.x)),
                 0x15 => Ok(SetDelay(dec.x)),
                 0x18 => Ok(SetSound(dec.x)),
                 0x1E => Ok(AddToIndex(dec.x)),
                 0x29 => Ok(LoadFont(dec.x)),
+                0x30 => Ok(LoadBigFont(dec.x)),
                 0x33 => Ok(LoadBCD(dec.x)),
+                0x3A => Ok(SetPitch(dec.x)),
                 0x55 => Ok(StoreRegs(dec.x)),
                 0x65 => Ok(LoadRegs(dec.x)),
+                0x75 => Ok(StoreFlags(dec.x)),
+                0x85 => Ok(LoadFlags(dec.x)),
                 _ => Err(Chip9Error::UnrecognizedOpcode(dec.code)),
             },
 
             _ => Err(Chip9Error::UnrecognizedOpcode(dec.code)),
         }
     }
-}
\ No newline at end of file
+}
+
+// Register operand
+struct V(Nib);
+
+impl fmt::Display for V {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "V{:X}", self.0.value())
+    }
+}
+
+impl fmt::Display for OpCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write_mnemonic(f, &|_| None)
+    }
+}
+
+impl OpCode {
+    /// Formats the instruction with address operands replaced by the names `label` returns
+    pub fn format_with(&self, label: impl Fn(u16) -> Option<String>) -> String {
+        let mut mnemonic = String::new();
+        let _ = self.write_mnemonic(&mut mnemonic, &label);
+        mnemonic
+    }
+
+    fn write_mnemonic(&self, f: &mut impl fmt::Write, label: &dyn Fn(u16) -> Option<String>) -> fmt::Result {
+        use OpCode::*;
+
+        let addr = |addr: Addr| label(addr.value()).unwrap_or_else(|| addr.to_string());
+        match *self {
+            NoOp => write!(f, "NOP"),
+            ScrollDown(n) => write!(f, "SCD {}", n.value()),
+            ScrollUp(n) => write!(f, "SCU {}", n.value()),
+            ClearScreen => write!(f, "CLS"),
+            Return => write!(f, "RET"),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            LowRes => write!(f, "LOW"),
+            HighRes => write!(f, "HIGH"),
+            Jump(a) => write!(f, "JP {}", addr(a)),
+            Call(a) => write!(f, "CALL {}", addr(a)),
+            SkipEqualByte(x, kk) => write!(f, "SE {}, 0x{:02X}", V(x), kk),
+            SkipNotEqualByte(x, kk) => write!(f, "SNE {}, 0x{:02X}", V(x), kk),
+            SkipEqualReg(x, y) => write!(f, "SE {}, {}", V(x), V(y)),
+            StoreRange(x, y) => write!(f, "LD [I], {}-{}", V(x), V(y)),
+            LoadRange(x, y) => write!(f, "LD {}-{}, [I]", V(x), V(y)),
+            LoadByte(x, kk) => write!(f, "LD {}, 0x{:02X}", V(x), kk),
+            AddByte(x, kk) => write!(f, "ADD {}, 0x{:02X}", V(x), kk),
+            LoadReg(x, y) => write!(f, "LD {}, {}", V(x), V(y)),
+            OrReg(x, y) => write!(f, "OR {}, {}", V(x), V(y)),
+            AndReg(x, y) => write!(f, "AND {}, {}", V(x), V(y)),
+            XorReg(x, y) => write!(f, "XOR {}, {}", V(x), V(y)),
+            AddReg(x, y) => write!(f, "ADD {}, {}", V(x), V(y)),
+            SubReg(x, y) => write!(f, "SUB {}, {}", V(x), V(y)),
+            ShiftRight(x, y) => write!(f, "SHR {}, {}", V(x), V(y)),
+            SubNot(x, y) => write!(f, "SUBN {}, {}", V(x), V(y)),
+            ShiftLeft(x, y) => write!(f, "SHL {}, {}", V(x), V(y)),
+            SkipNotEqualReg(x, y) => write!(f, "SNE {}, {}", V(x), V(y)),
+            LoadIndex(a) => write!(f, "LD I, {}", addr(a)),
+            JumpV0(a) => write!(f, "JP V0, {}", addr(a)),
+            RandomByte(x, kk) => write!(f, "RND {}, 0x{:02X}", V(x), kk),
+            Draw(x, y, n) => write!(f, "DRW {}, {}, {}", V(x), V(y), n.value()),
+            SkipKeyPressed(x) => write!(f, "SKP {}", V(x)),
+            SkipKeyNotPressed(x) => write!(f, "SKNP {}", V(x)),
+            LoadLongIndex(a) => write!(f, "LD I, LONG {}", label(a.value()).unwrap_or_else(|| format!("0x{:04X}", a.value()))),
+            SelectPlanes(n) => write!(f, "PLANE {}", n.value()),
+            LoadAudio => write!(f, "AUDIO"),
+            LoadDelay(x) => write!(f, "LD {}, DT", V(x)),
+            WaitKey(x) => write!(f, "LD {}, K", V(x)),
+            SetDelay(x) => write!(f, "LD DT, {}", V(x)),
+            SetSound(x) => write!(f, "LD ST, {}", V(x)),
+            AddToIndex(x) => write!(f, "ADD I, {}", V(x)),
+            LoadFont(x) => write!(f, "LD F, {}", V(x)),
+            LoadBigFont(x) => write!(f, "LD HF, {}", V(x)),
+            LoadBCD(x) => write!(f, "LD B, {}", V(x)),
+            SetPitch(x) => write!(f, "PITCH {}", V(x)),
+            StoreRegs(x) => write!(f, "LD [I], {}", V(x)),
+            LoadRegs(x) => write!(f, "LD {}, [I]", V(x)),
+            StoreFlags(x) => write!(f, "LD R, {}", V(x)),
+            LoadFlags(x) => write!(f, "LD {}, R", V(x)),
+        }
+    }
+}
@@ -1,6 +1,8 @@
 use std::ops::{Index, IndexMut};
 
 use super::opcode::Nib;
+use crate::chip9::state::{StateReader, StateWriter};
+use crate::errors::Chip9Error;
 
 const NUM_REGISTERS: usize = 16;
 const FLAG_REGISTER: usize = 0xF;
@@ -21,6 +23,15 @@ impl Registers {
     pub fn set_flag(&mut self, val: u8) {
         self.regs[FLAG_REGISTER] = val;
     }
+
+    pub fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bytes(&self.regs);
+    }
+
+    pub fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Chip9Error> {
+        self.regs.copy_from_slice(reader.read_bytes(NUM_REGISTERS)?);
+        Ok(())
+    }
 }
 
 impl Index<Nib> for Registers {
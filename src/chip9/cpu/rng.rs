@@ -0,0 +1,38 @@
+/// Source of the random bytes masked by CXKK
+pub trait RandomSource: Send {
+    fn next_byte(&mut self) -> u8;
+
+    /// Internal state, stored in save states so that a restored session draws the same bytes
+    fn state(&self) -> u64;
+
+    fn set_state(&mut self, state: u64);
+}
+
+/// SplitMix64, the default source. The same seed always gives the same sequence
+pub struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+}
+
+impl RandomSource for SplitMix64 {
+    fn next_byte(&mut self) -> u8 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        (z ^ (z >> 31)) as u8
+    }
+
+    fn state(&self) -> u64 {
+        self.state
+    }
+
+    fn set_state(&mut self, state: u64) {
+        self.state = state;
+    }
+}
@@ -1,38 +1,302 @@
-pub const DISPLAY_WIDTH: usize = 64;
-pub const DISPLAY_HEIGHT: usize = 32;
+use crate::chip9::state::{StateReader, StateWriter};
+use crate::errors::Chip9Error;
 
+pub const DISPLAY_WIDTH: usize = 128;
+pub const DISPLAY_HEIGHT: usize = 64;
+pub const LORES_WIDTH: usize = 64;
+pub const LORES_HEIGHT: usize = 32;
+pub const DISPLAY_PLANES: usize = 2;
+
+const SCROLL_STEP: usize = 4;
+
+/// One row per element, the leftmost pixel in the most significant bit
+pub type Plane = [u128; DISPLAY_HEIGHT];
+
+const EMPTY_PLANE: Plane = [0; DISPLAY_HEIGHT];
 
 pub struct Display {
-    pub grid: [[bool; DISPLAY_HEIGHT]; DISPLAY_WIDTH], // todo: refactor
+    planes: [Plane; DISPLAY_PLANES],
+    selected: u8, // bitmask of the planes affected by drawing, clearing and scrolling
+    hires: bool,
+    generation: u64, // bumped whenever pixels may have changed
+}
+
+impl Default for Display {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Display {
     pub fn new() -> Self {
-        let grid = [[false; DISPLAY_HEIGHT]; DISPLAY_WIDTH];
+        let planes = [EMPTY_PLANE; DISPLAY_PLANES];
 
-        Self { grid }
+        Self { planes, selected: 0b01, hires: false, generation: 0 }
+    }
+
+    /// Width of the active resolution
+    pub fn width(&self) -> usize {
+        if self.hires { DISPLAY_WIDTH } else { LORES_WIDTH }
+    }
+
+    /// Height of the active resolution
+    pub fn height(&self) -> usize {
+        if self.hires { DISPLAY_HEIGHT } else { LORES_HEIGHT }
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Counts the changes to the display: draws, clears, scrolls, resolution switches and
+    /// loaded states. A frontend can skip presenting while it stays the same
+    pub fn generation(&self) -> u64 {
+        self.generation
+    }
+
+    /// Color index of the pixel, bit N is set when the pixel is lit on plane N
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        let bit = 1 << (u128::BITS as usize - 1 - x);
+        self.planes.iter()
+            .enumerate()
+            .fold(0, |color, (i, plane)| color | (((plane[y] & bit != 0) as u8) << i))
+    }
+
+    /// Pixels of a single plane as packed rows. In lores only the top left 64x32 pixels are used
+    pub fn plane(&self, plane: usize) -> &Plane {
+        &self.planes[plane]
+    }
+
+    // Planes are stored row by row, 16 big-endian bytes per row
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_bool(self.hires);
+        writer.write_u8(self.selected);
+        for row in self.planes.iter().flatten() {
+            writer.write_bytes(&row.to_be_bytes());
+        }
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Chip9Error> {
+        self.generation += 1;
+        self.hires = reader.read_bool()?;
+        self.select_planes(reader.read_u8()?);
+        for row in self.planes.iter_mut().flatten() {
+            *row = u128::from_be_bytes(reader.read_bytes(size_of::<u128>())?.try_into().unwrap());
+        }
+        Ok(())
+    }
+
+    // Bits of the rows that are on screen in the active resolution
+    fn row_mask(&self) -> u128 {
+        !0 << (u128::BITS as usize - self.width())
+    }
+
+    pub(super) fn set_hires(&mut self, hires: bool) {
+        self.generation += 1;
+        self.hires = hires;
+        self.planes = [EMPTY_PLANE; DISPLAY_PLANES];
+    }
+
+    pub(super) fn select_planes(&mut self, mask: u8) {
+        self.selected = mask & ((1 << DISPLAY_PLANES) - 1);
+    }
+
+    pub(super) fn selected_planes(&self) -> impl Iterator<Item = usize> + use<> {
+        let selected = self.selected;
+        (0..DISPLAY_PLANES).filter(move |plane| selected & (1 << plane) != 0)
     }
 
     pub(super) fn clear(&mut self) {
-        self.grid = [[false; DISPLAY_HEIGHT]; DISPLAY_WIDTH];
-    }
-
-    pub(super) fn draw(&mut self, horizontal_pos: usize, vertical_pos: usize, sprite: impl Iterator<Item = u8>) -> bool {
-        let mut collision = false;
-        for (j, byte) in sprite.enumerate() {
-            for i in 0..8 {
-                let xi = (horizontal_pos + i) % DISPLAY_WIDTH;
-                let yj = (vertical_pos + j) % DISPLAY_HEIGHT;
-                let old = self.grid[xi][yj];
-                let new = (byte & (0x80 >> i)) != 0;
-                self.grid[xi][yj] ^= new;
-                collision |= old && !self.grid[xi][yj];
+        self.generation += 1;
+        for plane in self.selected_planes() {
+            self.planes[plane] = EMPTY_PLANE;
+        }
+    }
+
+    /// Draws `width` bits wide sprite rows (MSB first) and returns the number of rows that collided.
+    /// The starting position always wraps, the rest of the sprite is either clipped or wrapped.
+    pub(super) fn draw(&mut self, plane: usize, horizontal_pos: usize, vertical_pos: usize, sprite: impl Iterator<Item = u16>, width: usize, clip: bool) -> u8 {
+        self.generation += 1;
+        let (display_width, display_height) = (self.width(), self.height());
+        let horizontal_pos = horizontal_pos % display_width;
+        let vertical_pos = vertical_pos % display_height;
+        let mask = self.row_mask();
+        let rows = &mut self.planes[plane];
+
+        let mut collisions = 0;
+        for (j, row) in sprite.enumerate() {
+            let yj = vertical_pos + j;
+            if clip && yj >= display_height {
+                break;
+            }
+            let yj = yj % display_height;
+
+            // The sprite row at the left edge, then moved into place. Pixels past the
+            // right edge end up in `wrapped` at the left edge
+            let sprite_row = (row as u128) << (u128::BITS as usize - width);
+            let mut pixels = (sprite_row >> horizontal_pos) & mask;
+            if !clip {
+                let wrapped = sprite_row.checked_shl((display_width - horizontal_pos) as u32).unwrap_or(0);
+                pixels |= wrapped & mask;
+            }
+
+            collisions += (rows[yj] & pixels != 0) as u8;
+            rows[yj] ^= pixels;
+        }
+        collisions
+    }
+
+    pub(super) fn scroll_down(&mut self, rows: usize) {
+        self.generation += 1;
+        let height = self.height();
+        let rows = rows.min(height);
+        for plane in self.selected_planes() {
+            let plane = &mut self.planes[plane];
+            plane.copy_within(0..height - rows, rows);
+            plane[..rows].fill(0);
+        }
+    }
+
+    pub(super) fn scroll_up(&mut self, rows: usize) {
+        self.generation += 1;
+        let height = self.height();
+        let rows = rows.min(height);
+        for plane in self.selected_planes() {
+            let plane = &mut self.planes[plane];
+            plane.copy_within(rows..height, 0);
+            plane[height - rows..height].fill(0);
+        }
+    }
+
+    pub(super) fn scroll_right(&mut self) {
+        self.generation += 1;
+        let mask = self.row_mask();
+        for plane in self.selected_planes() {
+            for row in self.planes[plane].iter_mut() {
+                *row = (*row >> SCROLL_STEP) & mask;
             }
         }
-        collision
     }
 
-    pub fn grid(&self) -> &[[bool; DISPLAY_HEIGHT]; DISPLAY_WIDTH] {
-        &self.grid
+    pub(super) fn scroll_left(&mut self) {
+        self.generation += 1;
+        for plane in self.selected_planes() {
+            for row in self.planes[plane].iter_mut() {
+                *row <<= SCROLL_STEP;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn display(hires: bool) -> Display {
+        let mut display = Display::new();
+        display.set_hires(hires);
+        display
+    }
+
+    fn lit(display: &Display, y: usize) -> Vec<usize> {
+        (0..display.width()).filter(|&x| display.pixel(x, y) != 0).collect()
+    }
+
+    // Pixel by pixel drawing on a grid of bools, as the display was drawn before rows were packed
+    fn draw_grid(grid: &mut [[bool; DISPLAY_WIDTH]; DISPLAY_HEIGHT], (width, height): (usize, usize), x: usize, y: usize, sprite: &[u16], sprite_width: usize, clip: bool) -> u8 {
+        let (x, y) = (x % width, y % height);
+        let mut collisions = 0;
+        for (j, row) in sprite.iter().enumerate() {
+            if clip && y + j >= height {
+                break;
+            }
+            let mut collided = false;
+            for i in (0..sprite_width).filter(|i| row & (1 << (sprite_width - 1 - i)) != 0) {
+                if clip && x + i >= width {
+                    continue;
+                }
+                let pixel = &mut grid[(y + j) % height][(x + i) % width];
+                collided |= *pixel;
+                *pixel = !*pixel;
+            }
+            collisions += collided as u8;
+        }
+        collisions
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn sprites_wrap_or_clip_at_the_right_edge() {
+        for hires in [false, true] {
+            let mut wrapped = display(hires);
+            let mut clipped = display(hires);
+            let x = wrapped.width() - 4;
+            wrapped.draw(0, x, 0, [0xFF].into_iter(), 8, false);
+            clipped.draw(0, x, 0, [0xFF].into_iter(), 8, true);
+
+            let right_edge: Vec<usize> = (x..wrapped.width()).collect();
+            assert_eq!(lit(&wrapped, 0), [vec![0, 1, 2, 3], right_edge.clone()].concat());
+            assert_eq!(lit(&clipped, 0), right_edge);
+        }
+    }
+
+    #[test]
+    fn sprites_wrap_or_clip_at_the_bottom_edge() {
+        let mut wrapped = display(false);
+        let mut clipped = display(false);
+        wrapped.draw(0, 0, 31, [0x80, 0x80, 0x80].into_iter(), 8, false);
+        clipped.draw(0, 0, 31, [0x80, 0x80, 0x80].into_iter(), 8, true);
+
+        assert_eq!((0..32).filter(|&y| wrapped.pixel(0, y) != 0).collect::<Vec<_>>(), [0, 1, 31]);
+        assert_eq!((0..32).filter(|&y| clipped.pixel(0, y) != 0).collect::<Vec<_>>(), [31]);
+    }
+
+    #[test]
+    fn starting_position_always_wraps() {
+        let mut display = display(false);
+        display.draw(0, 66, 33, [0x80].into_iter(), 8, true);
+        assert_eq!(display.pixel(2, 1), 1);
+    }
+
+    #[test]
+    fn big_sprites_are_16_pixels_wide() {
+        let mut display = display(true);
+        display.draw(0, 8, 0, [0xFFFF, 0x8001].into_iter(), 16, true);
+
+        assert_eq!(lit(&display, 0), (8..24).collect::<Vec<_>>());
+        assert_eq!(lit(&display, 1), [8, 23]);
+    }
+
+    #[test]
+    fn collisions_are_counted_per_row() {
+        let mut display = display(true);
+        assert_eq!(display.draw(0, 0, 0, [0xF0, 0xF0, 0xF0].into_iter(), 8, true), 0);
+        assert_eq!(display.draw(0, 0, 0, [0x80, 0x00, 0x08].into_iter(), 8, true), 1);
+        assert_eq!(display.draw(0, 2, 0, [0x80, 0x80, 0x80].into_iter(), 8, true), 3);
+    }
+
+    #[test]
+    fn packed_rows_match_a_pixel_grid() {
+        let sprite: Vec<u16> = (0..16u16).map(|i| i.wrapping_mul(0x9E37) ^ 0xA5C3).collect();
+        for hires in [false, true] {
+            for clip in [false, true] {
+                for sprite_width in [8, 16] {
+                    let mut display = display(hires);
+                    let mut grid = [[false; DISPLAY_WIDTH]; DISPLAY_HEIGHT];
+                    let size = (display.width(), display.height());
+                    let sprite: Vec<u16> = sprite.iter().map(|row| row >> (16 - sprite_width)).collect();
+
+                    for (x, y) in (0..200).step_by(7).zip((0..100).step_by(3)) {
+                        let expected = draw_grid(&mut grid, size, x, y, &sprite, sprite_width, clip);
+                        let collisions = display.draw(0, x, y, sprite.iter().copied(), sprite_width, clip);
+                        assert_eq!(collisions, expected, "collisions at {}, {}", x, y);
+                    }
+                    for (y, row) in grid.iter().enumerate().take(size.1) {
+                        for (x, &pixel) in row.iter().enumerate().take(size.0) {
+                            assert_eq!(display.pixel(x, y), pixel as u8, "pixel {}, {}", x, y);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
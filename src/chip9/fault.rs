@@ -0,0 +1,76 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// What the CPU does when a program goes somewhere the hardware can't
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FaultAction {
+    /// Stop with an error
+    #[default]
+    Fault,
+    /// Wrap the stack pointer around the stack, or the address around the end of memory
+    Wrap,
+    /// Skip the offending instruction
+    Ignore,
+}
+
+impl FaultAction {
+    pub const ALL: [FaultAction; 3] = [FaultAction::Fault, FaultAction::Wrap, FaultAction::Ignore];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FaultAction::Fault => "fault",
+            FaultAction::Wrap => "wrap",
+            FaultAction::Ignore => "ignore",
+        }
+    }
+}
+
+impl fmt::Display for FaultAction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for FaultAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        FaultAction::ALL.into_iter()
+            .find(|action| action.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = FaultAction::ALL.iter().map(FaultAction::name).collect();
+                format!("unknown fault action '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// Action taken for each kind of fault
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FaultPolicy {
+    /// 2nnn with all 16 stack entries in use
+    pub stack_overflow: FaultAction,
+    /// 00EE with an empty stack
+    pub stack_underflow: FaultAction,
+    /// PC past the end of memory
+    pub pc_out_of_range: FaultAction,
+    /// Memory accessed through I past the end of memory
+    pub index_out_of_range: FaultAction,
+}
+
+/// Machine state at the faulting instruction
+#[derive(Clone, Debug)]
+pub struct FaultContext {
+    pub pc: u16,
+    pub opcode: u16,
+    pub registers: [u8; 16],
+    pub index: u16,
+    pub stack_pointer: u8,
+}
+
+impl fmt::Display for FaultContext {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let registers: Vec<String> = self.registers.iter().map(|reg| format!("{:02X}", reg)).collect();
+        write!(f, "PC=0x{:03X} opcode={:04X} I=0x{:03X} SP={} V=[{}]",
+            self.pc, self.opcode, self.index, self.stack_pointer, registers.join(" "))
+    }
+}
@@ -1,7 +1,16 @@
+use crate::chip9::state::{StateReader, StateWriter};
+use crate::errors::Chip9Error;
+
 pub struct Keyboard {
     pressed: [bool; 16],
 }
 
+impl Default for Keyboard {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl Keyboard {
     pub fn new() -> Self {
         Self { pressed: [false; 16] }
@@ -22,6 +31,28 @@ impl Keyboard {
         self.pressed.get(chip9_key as usize).copied().unwrap_or(false)
     }
 
+    /// Pressed keys as bits, bit n set for key n
+    pub fn mask(&self) -> u16 {
+        self.pressed.iter()
+            .enumerate()
+            .fold(0u16, |bits, (i, &pressed)| bits | ((pressed as u16) << i))
+    }
+
+    pub fn set_mask(&mut self, bits: u16) {
+        for (i, pressed) in self.pressed.iter_mut().enumerate() {
+            *pressed = bits & (1 << i) != 0;
+        }
+    }
+
+    pub(super) fn save_state(&self, writer: &mut StateWriter) {
+        writer.write_u16(self.mask());
+    }
+
+    pub(super) fn load_state(&mut self, reader: &mut StateReader) -> Result<(), Chip9Error> {
+        self.set_mask(reader.read_u16()?);
+        Ok(())
+    }
+
     /// Get the first pressed CHIP-8 key, if any
     pub fn get_key_press(&self) -> Option<u8> {
         self.pressed.iter()
@@ -0,0 +1,255 @@
+use std::fmt;
+use std::str::FromStr;
+
+/// Behavior switches for the instructions that differ between CHIP-8 interpreters.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+    /// 8xy6/8xyE shift Vy into Vx instead of shifting Vx in place
+    pub shift_uses_vy: bool,
+    /// How far Fx55/Fx65 move I
+    pub load_store_increment: IndexIncrement,
+    /// Bxnn jumps to xnn + Vx instead of nnn + V0
+    pub jump_uses_vx: bool,
+    /// 8xy1/8xy2/8xy3 reset VF to 0
+    pub vf_reset: bool,
+    /// Sprites are clipped at the screen edges instead of wrapping around
+    pub clip_sprites: bool,
+    /// Dxyn waits for the next vertical blank before drawing
+    pub display_wait: bool,
+    /// Dxyn in hires sets VF to the number of rows that collided or were clipped at the bottom
+    pub row_collision: bool,
+    /// Memory is 64K, as on XO-CHIP, instead of 4K
+    pub extended_memory: bool,
+}
+
+/// Where Fx55/Fx65 leave I
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+    /// I is left untouched
+    None,
+    /// I points at the last register transferred, a CHIP-48 bug
+    X,
+    /// I points past the last register transferred, as on the COSMAC VIP
+    XPlusOne,
+}
+
+impl IndexIncrement {
+    pub(super) fn amount(&self, x: u8) -> u16 {
+        match self {
+            IndexIncrement::None => 0,
+            IndexIncrement::X => x as u16,
+            IndexIncrement::XPlusOne => x as u16 + 1,
+        }
+    }
+}
+
+impl Quirks {
+    pub const COSMAC_VIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increment: IndexIncrement::XPlusOne,
+        jump_uses_vx: false,
+        vf_reset: true,
+        clip_sprites: true,
+        display_wait: true,
+        row_collision: false,
+        extended_memory: false,
+    };
+
+    pub const CHIP_48: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: IndexIncrement::X,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        row_collision: false,
+        extended_memory: false,
+    };
+
+    pub const SCHIP: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: IndexIncrement::None,
+        jump_uses_vx: true,
+        vf_reset: false,
+        clip_sprites: true,
+        display_wait: false,
+        row_collision: true,
+        extended_memory: false,
+    };
+
+    pub const XO_CHIP: Quirks = Quirks {
+        shift_uses_vy: true,
+        load_store_increment: IndexIncrement::XPlusOne,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+        row_collision: false,
+        extended_memory: true,
+    };
+}
+
+impl Default for Quirks {
+    fn default() -> Self {
+        Preset::default().quirks()
+    }
+}
+
+/// Named quirk profiles of well-known interpreters
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Preset {
+    #[default]
+    CosmacVip,
+    Chip48,
+    Schip,
+    XoChip,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 4] = [Preset::CosmacVip, Preset::Chip48, Preset::Schip, Preset::XoChip];
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Preset::CosmacVip => Quirks::COSMAC_VIP,
+            Preset::Chip48 => Quirks::CHIP_48,
+            Preset::Schip => Quirks::SCHIP,
+            Preset::XoChip => Quirks::XO_CHIP,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::CosmacVip => "vip",
+            Preset::Chip48 => "chip48",
+            Preset::Schip => "schip",
+            Preset::XoChip => "xochip",
+        }
+    }
+}
+
+impl fmt::Display for Preset {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Preset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Preset::ALL.into_iter()
+            .find(|preset| preset.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Preset::ALL.iter().map(Preset::name).collect();
+                format!("unknown quirks preset '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Chip9;
+    use crate::errors::Chip9Error;
+
+    // Every quirk off, each test turns one on
+    const NONE: Quirks = Quirks {
+        shift_uses_vy: false,
+        load_store_increment: IndexIncrement::None,
+        jump_uses_vx: false,
+        vf_reset: false,
+        clip_sprites: false,
+        display_wait: false,
+        row_collision: false,
+        extended_memory: false,
+    };
+
+    fn run(quirks: Quirks, rom: &[u8], ticks: usize) -> Chip9 {
+        let mut chip9 = Chip9::new(quirks);
+        chip9.load_rom(rom).unwrap();
+        for _ in 0..ticks {
+            chip9.tick().unwrap();
+        }
+        chip9
+    }
+
+    fn registers(quirks: Quirks, rom: &[u8], ticks: usize) -> [u8; 16] {
+        run(quirks, rom, ticks).cpu().registers()
+    }
+
+    #[test]
+    fn shift_uses_vy() {
+        let rom = [0x60, 0x03, 0x61, 0x10, 0x80, 0x16]; // V0 := 3, V1 := 0x10, V0 >>= V1
+        let with = registers(Quirks { shift_uses_vy: true, ..NONE }, &rom, 3);
+        let without = registers(NONE, &rom, 3);
+
+        assert_eq!((with[0], with[0xF]), (0x08, 0));
+        assert_eq!((without[0], without[0xF]), (0x01, 1));
+    }
+
+    #[test]
+    fn load_store_increment() {
+        let rom = [0xA3, 0x00, 0xF2, 0x55]; // I := 0x300, save V0-V2
+        let index = |load_store_increment| run(Quirks { load_store_increment, ..NONE }, &rom, 2).cpu().index();
+
+        assert_eq!(index(IndexIncrement::None), 0x300);
+        assert_eq!(index(IndexIncrement::X), 0x302);
+        assert_eq!(index(IndexIncrement::XPlusOne), 0x303);
+    }
+
+    #[test]
+    fn jump_uses_vx() {
+        let rom = [0x60, 0x02, 0x62, 0x10, 0xB2, 0x10]; // V0 := 2, V2 := 0x10, jump0 0x210
+        let pc = |quirks| run(quirks, &rom, 3).cpu().program_counter();
+
+        assert_eq!(pc(Quirks { jump_uses_vx: true, ..NONE }), 0x220);
+        assert_eq!(pc(NONE), 0x212);
+    }
+
+    #[test]
+    fn vf_reset() {
+        let rom = [0x6F, 0x05, 0x80, 0x11]; // VF := 5, V0 |= V1
+        assert_eq!(registers(Quirks { vf_reset: true, ..NONE }, &rom, 2)[0xF], 0);
+        assert_eq!(registers(NONE, &rom, 2)[0xF], 5);
+    }
+
+    #[test]
+    fn clip_sprites() {
+        let rom = [0x60, 0x3C, 0x61, 0x00, 0xA2, 0x08, 0xD0, 0x11, 0xFF]; // 8 pixels from x = 60
+        let clipped = run(Quirks { clip_sprites: true, ..NONE }, &rom, 4);
+        let wrapped = run(NONE, &rom, 4);
+
+        assert_eq!((clipped.display.pixel(63, 0), clipped.display.pixel(0, 0)), (1, 0));
+        assert_eq!((wrapped.display.pixel(63, 0), wrapped.display.pixel(3, 0)), (1, 1));
+    }
+
+    #[test]
+    fn display_wait() {
+        let rom = [0xD0, 0x05];
+        assert_eq!(run(Quirks { display_wait: true, ..NONE }, &rom, 1).cpu().program_counter(), 0x200);
+        assert_eq!(run(NONE, &rom, 1).cpu().program_counter(), 0x202);
+    }
+
+    #[test]
+    fn row_collision() {
+        // Hires, the same 3-row sprite drawn twice at 0, 0
+        let rom = [0x00, 0xFF, 0x60, 0x00, 0x61, 0x00, 0xA2, 0x0C, 0xD0, 0x13, 0xD0, 0x13, 0xFF, 0xFF, 0xFF];
+        assert_eq!(registers(Quirks { row_collision: true, ..NONE }, &rom, 6)[0xF], 3);
+        assert_eq!(registers(NONE, &rom, 6)[0xF], 1);
+
+        // Hires, a 3-row sprite drawn at 0, 62 clips one row, unless it wraps
+        let rom = [0x00, 0xFF, 0x60, 0x00, 0x61, 0x3E, 0xA2, 0x0A, 0xD0, 0x13, 0xFF, 0xFF, 0xFF];
+        assert_eq!(registers(Quirks { row_collision: true, clip_sprites: true, ..NONE }, &rom, 5)[0xF], 1);
+        assert_eq!(registers(Quirks { row_collision: true, ..NONE }, &rom, 5)[0xF], 0);
+    }
+
+    #[test]
+    fn extended_memory() {
+        let rom = [0xAF, 0xFE, 0xF2, 0x55]; // I := 0xFFE, save V0-V2
+        let mut chip9 = run(Quirks { extended_memory: true, ..NONE }, &rom, 1);
+        assert!(chip9.tick().is_ok());
+
+        let mut chip9 = run(NONE, &rom, 1);
+        assert!(matches!(chip9.tick(), Err(Chip9Error::IndexOutOfRange(_))));
+    }
+}
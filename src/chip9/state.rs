@@ -0,0 +1,105 @@
+use crate::errors::Chip9Error;
+
+const STATE_MAGIC: &[u8; 4] = b"C9ST";
+pub const STATE_VERSION: u16 = 6;
+
+/*
+    Save state layout, all values little-endian:
+
+        magic   - "C9ST"
+        version - u16, STATE_VERSION of the writer
+        body    - CPU, display and keyboard, in that order
+
+    The body has a fixed size for a given version, so a state of the wrong
+    length is rejected before anything is restored.
+*/
+pub(crate) struct StateWriter {
+    buf: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> Self {
+        Self { buf: Vec::new() }
+    }
+
+    pub fn with_header() -> Self {
+        let mut writer = Self::new();
+        writer.write_bytes(STATE_MAGIC);
+        writer.write_u16(STATE_VERSION);
+        writer
+    }
+
+    pub fn write_u8(&mut self, val: u8) {
+        self.buf.push(val);
+    }
+
+    pub fn write_u16(&mut self, val: u16) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, val: u64) {
+        self.buf.extend_from_slice(&val.to_le_bytes());
+    }
+
+    pub fn write_bool(&mut self, val: bool) {
+        self.write_u8(val as u8);
+    }
+
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.buf.extend_from_slice(bytes);
+    }
+
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.buf
+    }
+}
+
+pub(crate) struct StateReader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> StateReader<'a> {
+    /// Checks the header and returns a reader positioned at the body
+    pub fn with_header(data: &'a [u8]) -> Result<Self, Chip9Error> {
+        let mut reader = Self { data };
+        if reader.read_bytes(STATE_MAGIC.len())? != STATE_MAGIC {
+            return Err(Chip9Error::InvalidState("not a chip9 save state".to_string()));
+        }
+        let version = reader.read_u16()?;
+        if version != STATE_VERSION {
+            return Err(Chip9Error::UnsupportedStateVersion(version, STATE_VERSION));
+        }
+        Ok(reader)
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, Chip9Error> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, Chip9Error> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, Chip9Error> {
+        let bytes = self.read_bytes(8)?;
+        Ok(u64::from_le_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, Chip9Error> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], Chip9Error> {
+        if self.data.len() < len {
+            return Err(Chip9Error::InvalidState("unexpected end of save state".to_string()));
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+}
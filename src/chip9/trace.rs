@@ -0,0 +1,253 @@
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use crate::chip9::cpu::{CPU, OpCode};
+
+/*
+    One line per executed instruction, with space separated fields:
+
+        N=<instruction number> PC=<pc> OP=<raw opcode> V0=.. VF=.. I=<index> SP=<sp> DT=<dt> ST=<st> | <mnemonic>
+
+    Values are hex except N and SP. Traces of two runs are compared line by
+    line; the mnemonic is informational and isn't compared by `diff`.
+*/
+const MNEMONIC_SEPARATOR: &str = " | ";
+
+/// Which instructions are written to a trace
+#[derive(Clone, Debug)]
+pub struct TraceFilter {
+    pub pc_range: RangeInclusive<u16>,
+    pub skip: u64, // instructions executed before tracing starts
+    pub limit: Option<u64>, // lines written at most
+}
+
+impl Default for TraceFilter {
+    fn default() -> Self {
+        Self { pc_range: 0..=u16::MAX, skip: 0, limit: None }
+    }
+}
+
+/// PC range written as `START-END` in hex, both ends included
+#[derive(Clone, Debug)]
+pub struct PcRange(pub RangeInclusive<u16>);
+
+impl FromStr for PcRange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |value: &str| {
+            let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+            u16::from_str_radix(digits, 16).map_err(|_| format!("invalid address '{}'", value))
+        };
+        let (start, end) = s.split_once('-').ok_or_else(|| format!("expected START-END, found '{}'", s))?;
+        Ok(Self(parse(start)?..=parse(end)?))
+    }
+}
+
+/// Writes executed instructions with the machine state before each one
+pub struct Trace {
+    output: Box<dyn Write + Send>,
+    filter: TraceFilter,
+    executed: u64,
+    written: u64,
+}
+
+impl Trace {
+    pub fn new(output: Box<dyn Write + Send>, filter: TraceFilter) -> Self {
+        Self { output, filter, executed: 0, written: 0 }
+    }
+
+    pub(super) fn record(&mut self, cpu: &CPU) -> io::Result<()> {
+        self.executed += 1;
+        let pc = cpu.program_counter();
+        if self.executed <= self.filter.skip
+            || !self.filter.pc_range.contains(&pc)
+            || self.filter.limit.is_some_and(|limit| self.written >= limit) {
+            return Ok(());
+        }
+        self.written += 1;
+
+        let bytes = cpu.read_memory(pc, 4);
+        let size = OpCode::size(u16::from_be_bytes([bytes[0], bytes[1]])) as usize;
+        let raw: String = bytes[..size].iter().map(|byte| format!("{:02X}", byte)).collect();
+        let mnemonic = cpu.decode_at(pc).map_or_else(|_| "???".to_string(), |opcode| opcode.to_string());
+        let registers: Vec<String> = cpu.registers().iter().enumerate()
+            .map(|(i, reg)| format!("V{:X}={:02X}", i, reg))
+            .collect();
+
+        writeln!(self.output, "N={} PC={:04X} OP={} {} I={:04X} SP={} DT={:02X} ST={:02X}{}{}",
+            self.executed, pc, raw, registers.join(" "), cpu.index(), cpu.stack_pointer(),
+            cpu.delay_timer(), cpu.sound_timer(), MNEMONIC_SEPARATOR, mnemonic)
+    }
+}
+
+/// Compares two traces and reports the first line where they differ.
+/// Returns the line number of the divergence, if any
+pub fn diff(a: impl BufRead, b: impl BufRead, mut output: impl Write) -> io::Result<Option<usize>> {
+    let mut a = a.lines();
+    let mut b = b.lines();
+    let mut line = 0;
+
+    loop {
+        line += 1;
+        let (left, right) = match (a.next().transpose()?, b.next().transpose()?) {
+            (None, None) => {
+                writeln!(output, "Traces are identical ({} lines)", line - 1)?;
+                return Ok(None);
+            }
+            (left, right) => (left, right),
+        };
+
+        let left_fields = left.as_deref().map(fields).unwrap_or_default();
+        let right_fields = right.as_deref().map(fields).unwrap_or_default();
+        if left.is_some() && right.is_some() && left_fields == right_fields {
+            continue;
+        }
+
+        writeln!(output, "Traces diverge at line {}:", line)?;
+        writeln!(output, "< {}", left.as_deref().unwrap_or("(end of trace)"))?;
+        writeln!(output, "> {}", right.as_deref().unwrap_or("(end of trace)"))?;
+        for ((name, l), (_, r)) in left_fields.iter().zip(&right_fields).filter(|((_, l), (_, r))| l != r) {
+            writeln!(output, "  {}: {} != {}", name, l, r)?;
+        }
+        return Ok(Some(line));
+    }
+}
+
+// `NAME=value` pairs of a line, without the mnemonic
+fn fields(line: &str) -> Vec<(&str, &str)> {
+    let state = line.split(MNEMONIC_SEPARATOR).next().unwrap_or_default();
+    state.split_whitespace()
+        .map(|field| field.split_once('=').unwrap_or((field, "")))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::chip9::{Chip9, Quirks};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Lines written while running `ticks` instructions of `rom`
+    fn trace(rom: &[u8], filter: TraceFilter, ticks: usize) -> Vec<String> {
+        let output = SharedBuffer::default();
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(rom).unwrap();
+        chip9.set_trace(Trace::new(Box::new(output.clone()), filter));
+        for _ in 0..ticks {
+            chip9.tick().unwrap();
+        }
+        let bytes = output.0.lock().unwrap().clone();
+        String::from_utf8(bytes).unwrap().lines().map(str::to_string).collect()
+    }
+
+    // PC of each line
+    fn pcs(lines: &[String]) -> Vec<&str> {
+        lines.iter().map(|line| fields(line)[1].1).collect()
+    }
+
+    const COUNTER: [u8; 6] = [0x70, 0x01, 0x71, 0x02, 0x12, 0x00]; // V0 += 1, V1 += 2, repeat
+
+    fn diff_output(a: &str, b: &str) -> (Option<usize>, String) {
+        let mut output = Vec::new();
+        let line = diff(a.as_bytes(), b.as_bytes(), &mut output).unwrap();
+        (line, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn line_format() {
+        let lines = trace(&[0x60, 0x12, 0xA3, 0x45, 0x12, 0x00], TraceFilter::default(), 3);
+        let rest = "V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00 V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00";
+        assert_eq!(lines, [
+            format!("N=1 PC=0200 OP=6012 V0=00 {} I=0000 SP=0 DT=00 ST=00 | LD V0, 0x12", rest),
+            format!("N=2 PC=0202 OP=A345 V0=12 {} I=0000 SP=0 DT=00 ST=00 | LD I, 0x345", rest),
+            format!("N=3 PC=0204 OP=1200 V0=12 {} I=0345 SP=0 DT=00 ST=00 | JP 0x200", rest),
+        ]);
+    }
+
+    #[test]
+    fn skip_starts_later() {
+        let filter = TraceFilter { skip: 2, ..TraceFilter::default() };
+        let lines = trace(&COUNTER, filter, 5);
+        assert_eq!(pcs(&lines), ["0204", "0200", "0202"]);
+        assert!(lines[0].starts_with("N=3 "));
+    }
+
+    #[test]
+    fn limit_stops_writing() {
+        let filter = TraceFilter { limit: Some(2), ..TraceFilter::default() };
+        assert_eq!(pcs(&trace(&COUNTER, filter, 6)), ["0200", "0202"]);
+    }
+
+    #[test]
+    fn pc_range_selects_instructions() {
+        let filter = TraceFilter { pc_range: 0x202..=0x204, limit: Some(3), ..TraceFilter::default() };
+        let lines = trace(&COUNTER, filter, 9);
+        assert_eq!(pcs(&lines), ["0202", "0204", "0202"]);
+        assert!(lines[2].starts_with("N=5 "));
+    }
+
+    #[test]
+    fn pc_range_parses_hex() {
+        assert_eq!(PcRange::from_str("200-2FF").unwrap().0, 0x200..=0x2FF);
+        assert_eq!(PcRange::from_str("0x200-0XFFFF").unwrap().0, 0x200..=0xFFFF);
+        assert_eq!(PcRange::from_str("200").unwrap_err(), "expected START-END, found '200'");
+        assert_eq!(PcRange::from_str("200-2G0").unwrap_err(), "invalid address '2G0'");
+        assert_eq!(PcRange::from_str("-2FF").unwrap_err(), "invalid address ''");
+        assert_eq!(PcRange::from_str("200-10000").unwrap_err(), "invalid address '10000'");
+    }
+
+    #[test]
+    fn identical_traces() {
+        let lines = trace(&COUNTER, TraceFilter::default(), 4).join("\n");
+        assert_eq!(diff_output(&lines, &lines), (None, "Traces are identical (4 lines)\n".to_string()));
+    }
+
+    #[test]
+    fn mnemonics_are_not_compared() {
+        let (line, _) = diff_output("N=1 PC=0200 | CLS\n", "N=1 PC=0200 | ???\n");
+        assert_eq!(line, None);
+    }
+
+    #[test]
+    fn traces_diverging_in_one_field() {
+        let a = "N=1 PC=0200 V0=00 | LD V0, 0x01\nN=2 PC=0202 V0=01 | JP 0x200\n";
+        let b = "N=1 PC=0200 V0=00 | LD V0, 0x01\nN=2 PC=0202 V0=02 | JP 0x200\n";
+        assert_eq!(diff_output(a, b), (Some(2), [
+            "Traces diverge at line 2:",
+            "< N=2 PC=0202 V0=01 | JP 0x200",
+            "> N=2 PC=0202 V0=02 | JP 0x200",
+            "  V0: 01 != 02",
+            "",
+        ].join("\n")));
+    }
+
+    #[test]
+    fn traces_of_different_lengths() {
+        let a = "N=1 PC=0200\nN=2 PC=0202\n";
+        let b = "N=1 PC=0200\n";
+        assert_eq!(diff_output(a, b), (Some(2), [
+            "Traces diverge at line 2:",
+            "< N=2 PC=0202",
+            "> (end of trace)",
+            "",
+        ].join("\n")));
+        assert_eq!(diff_output(b, a).0, Some(2));
+    }
+}
@@ -0,0 +1,183 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::bindings::{BindingPreset, Bindings};
+use crate::chip9::Preset;
+use crate::errors::Chip9Error;
+use crate::palette::{Color, Palette, Theme};
+use crate::persistence::Persistence;
+
+/// Config file looked up in the working directory when none is given
+pub const DEFAULT_CONFIG_PATH: &str = "chip9.toml";
+
+/*
+    quirks = "vip"
+    ipf = 11
+    theme = "amber"                     # built-in palette
+    colors = ["#000000", "#FFFFFF"]     # background and foreground, or all 4 colors of the palette, instead of a theme
+    persistence = "light"               # off, light or heavy
+
+    [bindings]
+    preset = "qwerty"                   # qwerty, numpad or vip
+    5 = ["W", "Up"]                     # CHIP-8 key = host keys, replaces the preset's
+
+    [roms."Cave.ch8"]                   # overrides for one ROM, by file name
+    ipf = 30
+    bindings = { 5 = "Space" }
+*/
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    quirks: Option<String>,
+    ipf: Option<u32>,
+    theme: Option<String>,
+    colors: Option<Vec<String>>,
+    persistence: Option<String>,
+    bindings: BindingsSection,
+    roms: HashMap<String, RomSection>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+struct RomSection {
+    quirks: Option<String>,
+    ipf: Option<u32>,
+    theme: Option<String>,
+    colors: Option<Vec<String>>,
+    persistence: Option<String>,
+    bindings: BindingsSection,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default)]
+struct BindingsSection {
+    preset: Option<String>,
+    #[serde(flatten)]
+    keys: BTreeMap<String, HostKeys>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(untagged)]
+enum HostKeys {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// Settings for one ROM, fields left unset by the config are None
+#[derive(Debug)]
+pub struct Settings {
+    pub quirks: Option<Preset>,
+    pub ipf: Option<u32>,
+    pub theme: Option<Theme>, // the palette's theme, unless colors replace it
+    pub palette: Option<Palette>,
+    pub persistence: Option<Persistence>,
+    pub bindings: Bindings,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Self, Chip9Error> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| Chip9Error::ConfigError(format!("{}: {}", path.display(), e)))?;
+        toml::from_str(&text)
+            .map_err(|e| Chip9Error::ConfigError(format!("{}: {}", path.display(), e)))
+    }
+
+    /// Loads `path`, or the default config file if it exists
+    pub fn find(path: Option<&Path>) -> Result<Self, Chip9Error> {
+        let default = Path::new(DEFAULT_CONFIG_PATH);
+        match path {
+            Some(path) => Self::load(path),
+            None if default.exists() => Self::load(default),
+            None => Ok(Self::default()),
+        }
+    }
+
+    /// Settings for the ROM at `rom`, with its overrides applied over the top level ones
+    pub fn settings(&self, rom: &Path) -> Result<Settings, Chip9Error> {
+        let name = rom.file_name().map(|name| name.to_string_lossy()).unwrap_or_default();
+        let rom = self.roms.get(name.as_ref());
+
+        let quirks = rom.and_then(|rom| rom.quirks.as_ref()).or(self.quirks.as_ref())
+            .map(|quirks| quirks.parse())
+            .transpose()
+            .map_err(Chip9Error::ConfigError)?;
+        let ipf = rom.and_then(|rom| rom.ipf).or(self.ipf);
+        let (colors, theme) = match rom {
+            Some(rom) if rom.colors.is_some() || rom.theme.is_some() => (&rom.colors, &rom.theme),
+            _ => (&self.colors, &self.theme),
+        };
+        let theme = theme.as_ref()
+            .map(|theme| theme.parse::<Theme>())
+            .transpose()
+            .map_err(Chip9Error::ConfigError)?;
+        let palette = palette(colors, theme).map_err(Chip9Error::ConfigError)?;
+        let persistence = rom.and_then(|rom| rom.persistence.as_ref()).or(self.persistence.as_ref())
+            .map(|persistence| persistence.parse())
+            .transpose()
+            .map_err(Chip9Error::ConfigError)?;
+
+        let preset = rom.and_then(|rom| rom.bindings.preset.as_ref()).or(self.bindings.preset.as_ref())
+            .map(|preset| preset.parse::<BindingPreset>())
+            .transpose()
+            .map_err(Chip9Error::ConfigError)?
+            .unwrap_or_default();
+        let mut bindings = Bindings::preset(preset);
+        for section in [Some(&self.bindings), rom.map(|rom| &rom.bindings)].into_iter().flatten() {
+            bind_keys(&mut bindings, section).map_err(Chip9Error::ConfigError)?;
+        }
+
+        Ok(Settings { quirks, ipf, theme, palette, persistence, bindings })
+    }
+}
+
+fn bind_keys(bindings: &mut Bindings, section: &BindingsSection) -> Result<(), String> {
+    for (chip9_key, host_keys) in &section.keys {
+        let key = u8::from_str_radix(chip9_key, 16)
+            .map_err(|_| format!("'{}' isn't a CHIP-8 key, expected 0-F", chip9_key))?;
+        let host_keys = match host_keys {
+            HostKeys::One(host_key) => std::slice::from_ref(host_key),
+            HostKeys::Many(host_keys) => host_keys.as_slice(),
+        };
+        bindings.bind(key, host_keys)?;
+    }
+    Ok(())
+}
+
+// Colors take precedence over the theme
+fn palette(colors: &Option<Vec<String>>, theme: Option<Theme>) -> Result<Option<Palette>, String> {
+    if let Some(colors) = colors {
+        let colors = colors.iter()
+            .map(|color| color.parse())
+            .collect::<Result<Vec<Color>, _>>()?;
+        return Palette::from_colors(&colors).map(Some);
+    }
+    Ok(theme.map(|theme| theme.palette()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = r##"
+        theme = "amber"
+
+        [roms."Cave.ch8"]
+        theme = "lcd"
+
+        [roms."snake.ch8"]
+        colors = ["#000000", "#00FF00"]
+    "##;
+
+    #[test]
+    fn theme_comes_from_the_rom_or_the_top_level() {
+        let config: Config = toml::from_str(CONFIG).unwrap();
+        let theme = |rom: &str| config.settings(Path::new(rom)).unwrap().theme;
+
+        assert_eq!(theme("games/br8kout.ch8"), Some(Theme::Amber));
+        assert_eq!(theme("games/Cave.ch8"), Some(Theme::Lcd));
+        assert_eq!(theme("games/snake.ch8"), None);
+    }
+}
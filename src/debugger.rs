@@ -0,0 +1,394 @@
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+use crate::Chip9;
+use crate::chip9::DEFAULT_IPF;
+use crate::chip9::cpu::OpCode;
+use crate::errors::Chip9Error;
+use crate::screenshot;
+
+const PROMPT: &str = "(chip9) ";
+const DEFAULT_RUN_LIMIT: u64 = 10_000_000; // instructions, keeps `continue` from hanging in game loops
+const DEFAULT_DUMP_LEN: usize = 64;
+const DUMP_ROW: usize = 16;
+
+const HELP: &str = "\
+Commands:
+  s, step [n]           execute n instructions (default 1)
+  c, continue [n]       run until a breakpoint, an error, the program stops making progress
+                        or n instructions were executed (default 10000000)
+  f, finish [n]         run until the current subroutine returns, limited like continue
+  b, break [addr]       set a breakpoint on PC, list breakpoints without an address
+  d, delete [addr]      remove a breakpoint, all of them without an address
+  r, regs               print V0-VF, I, PC, SP, timers and the stack
+  m, mem addr [len]     dump len bytes of memory (default 64)
+  x, screen             print the display
+  k, keys [keys...]     hold the given hex keys, release all without arguments
+  h, help               print this help
+  q, quit               exit the debugger
+Addresses and keys are hexadecimal. An empty line repeats the last command.";
+
+// Why execution stopped
+enum Stop {
+    Steps,
+    Limit(u64),
+    Breakpoint(u16),
+    Returned,
+    Halted,
+    Stuck(&'static str),
+    Fault(Chip9Error),
+}
+
+/// Interactive command-line debugger driving a `Chip9` one instruction at a time
+pub struct Debugger {
+    chip9: Chip9,
+    breakpoints: BTreeSet<u16>,
+    executed: u64,
+}
+
+impl Debugger {
+    pub fn new(chip9: Chip9) -> Self {
+        Self { chip9, breakpoints: BTreeSet::new(), executed: 0 }
+    }
+
+    /// Reads commands from `input` until `quit` or end of input
+    pub fn run(&mut self, mut input: impl BufRead, mut output: impl Write) -> io::Result<()> {
+        let mut last = String::new();
+        self.print_location(&mut output)?;
+
+        loop {
+            write!(output, "{}", PROMPT)?;
+            output.flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(());
+            }
+            let line = if line.trim().is_empty() { last.clone() } else { line.trim().to_string() };
+            if line.is_empty() {
+                continue;
+            }
+
+            let mut words = line.split_whitespace();
+            let command = words.next().unwrap_or_default();
+            let args: Vec<&str> = words.collect();
+            match command {
+                "q" | "quit" => return Ok(()),
+                "h" | "help" => writeln!(output, "{}", HELP)?,
+                "s" | "step" => match args.first().map(|n| n.parse::<u64>()).unwrap_or(Ok(1)) {
+                    Ok(count) => self.step(count, &mut output)?,
+                    Err(_) => writeln!(output, "Invalid step count")?,
+                },
+                "c" | "continue" => match parse_limit(&args) {
+                    Some(limit) => {
+                        let stop = self.resume(limit, |_, _| false);
+                        self.report(stop, &mut output)?;
+                    }
+                    None => writeln!(output, "Invalid instruction count")?,
+                },
+                "f" | "finish" => match parse_limit(&args) {
+                    Some(limit) => self.finish(limit, &mut output)?,
+                    None => writeln!(output, "Invalid instruction count")?,
+                },
+                "b" | "break" => self.set_breakpoint(args.first().copied(), &mut output)?,
+                "d" | "delete" => self.delete_breakpoint(args.first().copied(), &mut output)?,
+                "r" | "regs" => self.print_registers(&mut output)?,
+                "m" | "mem" => self.dump_memory(&args, &mut output)?,
+                "x" | "screen" => self.print_screen(&mut output)?,
+                "k" | "keys" => self.hold_keys(&args, &mut output)?,
+                _ => writeln!(output, "Unknown command '{}', try 'help'", command)?,
+            }
+            last = line;
+        }
+    }
+
+    // Executes a single instruction, keeping vertical blanks at their usual pace
+    fn execute(&mut self) -> Result<(), Chip9Error> {
+        if self.executed.is_multiple_of(DEFAULT_IPF as u64) {
+            self.chip9.vblank();
+        }
+        self.executed += 1;
+        self.chip9.tick()
+    }
+
+    fn step(&mut self, count: u64, output: &mut impl Write) -> io::Result<()> {
+        for _ in 0..count {
+            self.print_instruction(self.chip9.cpu().program_counter(), output)?;
+            if self.chip9.is_halted() {
+                return self.report(Stop::Halted, output);
+            }
+            if let Err(e) = self.execute() {
+                return self.report(Stop::Fault(e), output);
+            }
+        }
+        self.report(Stop::Steps, output)
+    }
+
+    // Runs until a breakpoint or `done` returns true for the executed opcode and the new stack pointer
+    fn resume(&mut self, limit: u64, done: impl Fn(&OpCode, u8) -> bool) -> Stop {
+        for executed in 0..limit {
+            let cpu = self.chip9.cpu();
+            let pc = cpu.program_counter();
+            if executed > 0 && self.breakpoints.contains(&pc) {
+                return Stop::Breakpoint(pc);
+            }
+
+            if self.chip9.is_halted() {
+                return Stop::Halted;
+            }
+            let opcode = match cpu.decode_at(pc) {
+                Ok(opcode) => opcode,
+                Err(e) => return Stop::Fault(e),
+            };
+            if let Err(e) = self.execute() {
+                return Stop::Fault(e);
+            }
+
+            let cpu = self.chip9.cpu();
+            if done(&opcode, cpu.stack_pointer()) {
+                return Stop::Returned;
+            }
+            if cpu.program_counter() == pc {
+                match opcode {
+                    OpCode::WaitKey(_) => return Stop::Stuck("waiting for a key press"),
+                    OpCode::Jump(_) => return Stop::Stuck("jumping to itself"),
+                    _ => (),
+                }
+            }
+        }
+        Stop::Limit(limit)
+    }
+
+    fn finish(&mut self, limit: u64, output: &mut impl Write) -> io::Result<()> {
+        let depth = self.chip9.cpu().stack_pointer();
+        if depth == 0 {
+            return writeln!(output, "Not inside a subroutine");
+        }
+        let stop = self.resume(limit, |opcode, sp| matches!(opcode, OpCode::Return) && sp < depth);
+        self.report(stop, output)
+    }
+
+    fn report(&self, stop: Stop, output: &mut impl Write) -> io::Result<()> {
+        match stop {
+            Stop::Steps | Stop::Returned => (),
+            Stop::Limit(limit) => writeln!(output, "Stopped after {} instructions", limit)?,
+            Stop::Breakpoint(pc) => writeln!(output, "Breakpoint at 0x{:03X}", pc)?,
+            Stop::Halted => writeln!(output, "Program exited")?,
+            Stop::Stuck(reason) => writeln!(output, "Stopped, program is {}", reason)?,
+            Stop::Fault(e) => writeln!(output, "Error: {}", e)?,
+        }
+        self.print_location(output)
+    }
+
+    fn print_location(&self, output: &mut impl Write) -> io::Result<()> {
+        write!(output, "=> ")?;
+        self.print_instruction(self.chip9.cpu().program_counter(), output)
+    }
+
+    fn print_instruction(&self, addr: u16, output: &mut impl Write) -> io::Result<()> {
+        let cpu = self.chip9.cpu();
+        let word = cpu.read_memory(addr, 2);
+        let code = u16::from_be_bytes([word[0], word[1]]);
+        let raw: String = cpu.read_memory(addr, OpCode::size(code) as usize)
+            .iter()
+            .map(|byte| format!("{:02X}", byte))
+            .collect();
+        match cpu.decode_at(addr) {
+            Ok(opcode) => writeln!(output, "0x{:03X}: {:<8}  {}", addr, raw, opcode),
+            Err(_) => writeln!(output, "0x{:03X}: {:<8}  ???", addr, raw),
+        }
+    }
+
+    fn set_breakpoint(&mut self, addr: Option<&str>, output: &mut impl Write) -> io::Result<()> {
+        match addr.map(parse_hex) {
+            None if self.breakpoints.is_empty() => writeln!(output, "No breakpoints"),
+            None => {
+                for addr in &self.breakpoints {
+                    writeln!(output, "Breakpoint at 0x{:03X}", addr)?;
+                }
+                Ok(())
+            }
+            Some(Some(addr)) => {
+                self.breakpoints.insert(addr);
+                writeln!(output, "Breakpoint set at 0x{:03X}", addr)
+            }
+            Some(None) => writeln!(output, "Invalid address"),
+        }
+    }
+
+    fn delete_breakpoint(&mut self, addr: Option<&str>, output: &mut impl Write) -> io::Result<()> {
+        match addr.map(parse_hex) {
+            None => {
+                self.breakpoints.clear();
+                writeln!(output, "All breakpoints deleted")
+            }
+            Some(Some(addr)) if self.breakpoints.remove(&addr) => writeln!(output, "Breakpoint at 0x{:03X} deleted", addr),
+            Some(Some(addr)) => writeln!(output, "No breakpoint at 0x{:03X}", addr),
+            Some(None) => writeln!(output, "Invalid address"),
+        }
+    }
+
+    fn print_registers(&self, output: &mut impl Write) -> io::Result<()> {
+        let cpu = self.chip9.cpu();
+        for (row, regs) in cpu.registers().chunks(8).enumerate() {
+            let line: Vec<String> = regs.iter()
+                .enumerate()
+                .map(|(i, value)| format!("V{:X}={:02X}", row * 8 + i, value))
+                .collect();
+            writeln!(output, "{}", line.join(" "))?;
+        }
+        writeln!(output, "I={:04X} PC={:04X} SP={:02X} DT={:02X} ST={:02X}",
+            cpu.index(), cpu.program_counter(), cpu.stack_pointer(), cpu.delay_timer(), cpu.sound_timer())?;
+        let stack: Vec<String> = cpu.stack().iter().map(|addr| format!("{:04X}", addr)).collect();
+        writeln!(output, "Stack: [{}]", stack.join(" "))
+    }
+
+    fn dump_memory(&self, args: &[&str], output: &mut impl Write) -> io::Result<()> {
+        let Some(addr) = args.first().and_then(|addr| parse_hex(addr)) else {
+            return writeln!(output, "Usage: mem addr [len]");
+        };
+        let len = match args.get(1).map(|len| len.parse::<usize>()) {
+            None => DEFAULT_DUMP_LEN,
+            Some(Ok(len)) => len,
+            Some(Err(_)) => return writeln!(output, "Invalid length"),
+        };
+
+        let bytes = self.chip9.cpu().read_memory(addr, len);
+        for (row, chunk) in bytes.chunks(DUMP_ROW).enumerate() {
+            let hex: Vec<String> = chunk.iter().map(|byte| format!("{:02X}", byte)).collect();
+            writeln!(output, "0x{:04X}: {}", addr.wrapping_add((row * DUMP_ROW) as u16), hex.join(" "))?;
+        }
+        Ok(())
+    }
+
+    fn print_screen(&self, output: &mut impl Write) -> io::Result<()> {
+        screenshot::write_ascii(&self.chip9.display, output)
+    }
+
+    fn hold_keys(&mut self, args: &[&str], output: &mut impl Write) -> io::Result<()> {
+        let keys: Option<Vec<u8>> = args.iter()
+            .map(|key| parse_hex(key).filter(|&key| key < 16).map(|key| key as u8))
+            .collect();
+        match keys {
+            Some(keys) => {
+                self.chip9.keyboard.set_pressed(&keys);
+                writeln!(output, "Holding keys: {:X?}", keys)
+            }
+            None => writeln!(output, "Keys must be hexadecimal digits 0-F"),
+        }
+    }
+}
+
+fn parse_limit(args: &[&str]) -> Option<u64> {
+    args.first().map_or(Some(DEFAULT_RUN_LIMIT), |limit| limit.parse().ok())
+}
+
+fn parse_hex(value: &str) -> Option<u16> {
+    let digits = value.strip_prefix("0x").or_else(|| value.strip_prefix("0X")).unwrap_or(value);
+    u16::from_str_radix(digits, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip9::Quirks;
+
+    const PROGRAM: [u8; 12] = [
+        0x60, 0x05, // 0x200: V0 = 5
+        0x22, 0x08, // 0x202: call 0x208
+        0x71, 0x01, // 0x204: V1 += 1
+        0x12, 0x06, // 0x206: jump to itself
+        0x70, 0x02, // 0x208: V0 += 2
+        0x00, 0xEE, // 0x20A: return
+    ];
+
+    // Output of a debugger session running `commands` against `rom`, one per line
+    fn session(rom: &[u8], commands: &[&str]) -> String {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(rom).unwrap();
+        let input: String = commands.iter().map(|command| format!("{}\n", command)).collect();
+        let mut output = Vec::new();
+        Debugger::new(chip9).run(input.as_bytes(), &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn lines(lines: &[&str]) -> String {
+        lines.iter().map(|line| format!("{}\n", line)).collect::<String>() + PROMPT
+    }
+
+    #[test]
+    fn step_prints_executed_instructions() {
+        assert_eq!(session(&PROGRAM, &["s", "s 2", ""]), lines(&[
+            "=> 0x200: 6005      LD V0, 0x05",
+            "(chip9) 0x200: 6005      LD V0, 0x05",
+            "=> 0x202: 2208      CALL 0x208",
+            "(chip9) 0x202: 2208      CALL 0x208",
+            "0x208: 7002      ADD V0, 0x02",
+            "=> 0x20A: 00EE      RET",
+            "(chip9) 0x20A: 00EE      RET",
+            "0x204: 7101      ADD V1, 0x01",
+            "=> 0x206: 1206      JP 0x206",
+        ]));
+    }
+
+    #[test]
+    fn continue_stops_at_breakpoints() {
+        assert_eq!(session(&PROGRAM, &["b 208", "b 204", "b", "c", "c", "d 208", "d 208", "c"]), lines(&[
+            "=> 0x200: 6005      LD V0, 0x05",
+            "(chip9) Breakpoint set at 0x208",
+            "(chip9) Breakpoint set at 0x204",
+            "(chip9) Breakpoint at 0x204",
+            "Breakpoint at 0x208",
+            "(chip9) Breakpoint at 0x208",
+            "=> 0x208: 7002      ADD V0, 0x02",
+            "(chip9) Breakpoint at 0x204",
+            "=> 0x204: 7101      ADD V1, 0x01",
+            "(chip9) Breakpoint at 0x208 deleted",
+            "(chip9) No breakpoint at 0x208",
+            "(chip9) Stopped, program is jumping to itself",
+            "=> 0x206: 1206      JP 0x206",
+        ]));
+    }
+
+    #[test]
+    fn finish_runs_to_the_return() {
+        assert_eq!(session(&PROGRAM, &["f", "s 2", "f"]), lines(&[
+            "=> 0x200: 6005      LD V0, 0x05",
+            "(chip9) Not inside a subroutine",
+            "(chip9) 0x200: 6005      LD V0, 0x05",
+            "0x202: 2208      CALL 0x208",
+            "=> 0x208: 7002      ADD V0, 0x02",
+            "(chip9) => 0x204: 7101      ADD V1, 0x01",
+        ]));
+    }
+
+    #[test]
+    fn continue_stops_when_stuck() {
+        assert_eq!(session(&[0xF0, 0x0A], &["c"]), lines(&[
+            "=> 0x200: F00A      LD V0, K",
+            "(chip9) Stopped, program is waiting for a key press",
+            "=> 0x200: F00A      LD V0, K",
+        ]));
+        assert_eq!(session(&[0x12, 0x00], &["c 5"]).lines().nth(1), Some("(chip9) Stopped, program is jumping to itself"));
+        assert_eq!(session(&[0x70, 0x01, 0x12, 0x00], &["c 5"]).lines().nth(1), Some("(chip9) Stopped after 5 instructions"));
+    }
+
+    #[test]
+    fn regs_and_mem_print_the_state() {
+        assert_eq!(session(&PROGRAM, &["s 3", "r", "m 200 20", "m 20A 2", "m"]), lines(&[
+            "=> 0x200: 6005      LD V0, 0x05",
+            "(chip9) 0x200: 6005      LD V0, 0x05",
+            "0x202: 2208      CALL 0x208",
+            "0x208: 7002      ADD V0, 0x02",
+            "=> 0x20A: 00EE      RET",
+            "(chip9) V0=07 V1=00 V2=00 V3=00 V4=00 V5=00 V6=00 V7=00",
+            "V8=00 V9=00 VA=00 VB=00 VC=00 VD=00 VE=00 VF=00",
+            "I=0000 PC=020A SP=01 DT=00 ST=00",
+            "Stack: [0204]",
+            "(chip9) 0x0200: 60 05 22 08 71 01 12 06 70 02 00 EE 00 00 00 00",
+            "0x0210: 00 00 00 00",
+            "(chip9) 0x020A: 00 EE",
+            "(chip9) Usage: mem addr [len]",
+        ]));
+    }
+}
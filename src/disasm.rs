@@ -0,0 +1,192 @@
+use std::collections::BTreeMap;
+use std::io::{self, Write};
+
+use crate::chip9::cpu::{OpCode, PROGRAM_START};
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum LabelKind {
+    Data,  // I target
+    Label, // jump target
+    Sub,   // call target
+}
+
+impl LabelKind {
+    fn prefix(&self) -> &'static str {
+        match self {
+            LabelKind::Data => "data",
+            LabelKind::Label => "label",
+            LabelKind::Sub => "sub",
+        }
+    }
+}
+
+/// Disassembly of a ROM, code is told apart from data by following
+/// jumps, calls and skips from `PROGRAM_START`
+pub struct Disassembler<'a> {
+    rom: &'a [u8],
+    instructions: BTreeMap<u16, OpCode>, // decoded instructions by address
+    covered: Vec<bool>, // rom bytes belonging to an instruction
+    labels: BTreeMap<u16, LabelKind>,
+}
+
+impl<'a> Disassembler<'a> {
+    pub fn new(rom: &'a [u8]) -> Self {
+        let mut disassembler = Self {
+            rom,
+            instructions: BTreeMap::new(),
+            covered: vec![false; rom.len()],
+            labels: BTreeMap::new(),
+        };
+        disassembler.traverse();
+        disassembler
+    }
+
+    fn contains(&self, addr: u16) -> bool {
+        addr >= PROGRAM_START && ((addr - PROGRAM_START) as usize) < self.rom.len()
+    }
+
+    fn word(&self, addr: u16) -> u16 {
+        let byte = |addr: u16| if self.contains(addr) { self.rom[(addr - PROGRAM_START) as usize] } else { 0 };
+        u16::from_be_bytes([byte(addr), byte(addr.wrapping_add(1))])
+    }
+
+    fn add_label(&mut self, addr: u16, kind: LabelKind) {
+        if self.contains(addr) {
+            let label = self.labels.entry(addr).or_insert(kind);
+            *label = (*label).max(kind);
+        }
+    }
+
+    // Recursive descent over every reachable instruction
+    fn traverse(&mut self) {
+        let mut pending = vec![PROGRAM_START];
+
+        while let Some(addr) = pending.pop() {
+            if !self.contains(addr) || self.instructions.contains_key(&addr) {
+                continue;
+            }
+
+            let code = self.word(addr);
+            let size = OpCode::size(code);
+            let offset = (addr - PROGRAM_START) as usize;
+            let end = (offset + size as usize).min(self.rom.len());
+            let Ok(opcode) = OpCode::decode(code, self.word(addr.wrapping_add(2))) else {
+                continue;
+            };
+            if offset + size as usize > self.rom.len() || self.covered[offset..end].iter().any(|&covered| covered) {
+                continue;
+            }
+            self.covered[offset..end].fill(true);
+            self.instructions.insert(addr, opcode);
+
+            let next = addr.wrapping_add(size);
+            match opcode {
+                OpCode::Jump(target) => {
+                    self.add_label(target.value(), LabelKind::Label);
+                    pending.push(target.value());
+                }
+                OpCode::JumpV0(target) => {
+                    // Usually a jump table, its first entry is the best guess we have
+                    self.add_label(target.value(), LabelKind::Label);
+                    pending.push(target.value());
+                }
+                OpCode::Call(target) => {
+                    self.add_label(target.value(), LabelKind::Sub);
+                    pending.push(target.value());
+                    pending.push(next);
+                }
+                OpCode::Return | OpCode::Exit => (),
+                OpCode::SkipEqualByte(..) | OpCode::SkipNotEqualByte(..)
+                | OpCode::SkipEqualReg(..) | OpCode::SkipNotEqualReg(..)
+                | OpCode::SkipKeyPressed(_) | OpCode::SkipKeyNotPressed(_) => {
+                    pending.push(next);
+                    pending.push(next.wrapping_add(OpCode::size(self.word(next))));
+                }
+                OpCode::LoadIndex(target) | OpCode::LoadLongIndex(target) => {
+                    self.add_label(target.value(), LabelKind::Data);
+                    pending.push(next);
+                }
+                _ => pending.push(next),
+            }
+        }
+    }
+
+    fn label_name(&self, addr: u16) -> Option<String> {
+        self.labels.get(&addr).map(|kind| format!("{}_{:03X}", kind.prefix(), addr))
+    }
+
+    /// Writes the listing: labels, then address, raw bytes and mnemonic or data directive per line
+    pub fn write(&self, mut output: impl Write) -> io::Result<()> {
+        // Labels pointing inside an instruction can't be printed in place
+        for (&addr, _) in self.labels.iter().filter(|&(&addr, _)| self.is_inside_instruction(addr)) {
+            writeln!(output, "{} EQU 0x{:03X}", self.label_name(addr).unwrap(), addr)?;
+        }
+
+        let mut offset = 0;
+        while offset < self.rom.len() {
+            let Ok(addr) = u16::try_from(PROGRAM_START as usize + offset) else {
+                break; // past the end of the 64K address space, these bytes can't be loaded
+            };
+            if let Some(label) = self.label_name(addr) {
+                writeln!(output, "{}:", label)?;
+            }
+
+            match self.instructions.get(&addr) {
+                Some(opcode) => {
+                    let size = OpCode::size(self.word(addr)) as usize;
+                    let raw: String = self.rom[offset..offset + size].iter().map(|byte| format!("{:02X}", byte)).collect();
+                    let mnemonic = opcode.format_with(|target| self.label_name(target));
+                    writeln!(output, "    0x{:03X}  {:<8}  {}", addr, raw, mnemonic)?;
+                    offset += size;
+                }
+                None => {
+                    let byte = self.rom[offset];
+                    writeln!(output, "    0x{:03X}  {:02X}        DB 0x{:02X}  ; {}", addr, byte, byte, sprite_row(byte))?;
+                    offset += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn is_inside_instruction(&self, addr: u16) -> bool {
+        let offset = (addr - PROGRAM_START) as usize;
+        self.covered[offset] && !self.instructions.contains_key(&addr)
+    }
+}
+
+// Byte drawn as 8 sprite pixels
+fn sprite_row(byte: u8) -> String {
+    (0..8).map(|bit| if byte & (0x80 >> bit) != 0 { '#' } else { '.' }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listing(rom: &[u8]) -> String {
+        let mut output = Vec::new();
+        Disassembler::new(rom).write(&mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn code_and_data_are_told_apart() {
+        let listing = listing(&[0xA2, 0x04, 0x12, 0x02, 0xF0]); // I := data, loop, data
+        assert_eq!(listing.lines().collect::<Vec<_>>(), [
+            "    0x200  A204      LD I, data_204",
+            "label_202:",
+            "    0x202  1202      JP label_202",
+            "data_204:",
+            "    0x204  F0        DB 0xF0  ; ####....",
+        ]);
+    }
+
+    #[test]
+    fn rom_reaching_the_end_of_memory_is_listed() {
+        let mut rom = vec![0; 0x10000 - PROGRAM_START as usize]; // NOPs up to the last word
+        rom.push(0xAB); // past the end of memory
+        let listing = listing(&rom);
+        assert_eq!(listing.lines().last(), Some("    0xFFFE  0000      NOP"));
+    }
+}
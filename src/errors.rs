@@ -1,5 +1,7 @@
 use std::{fmt, error};
 
+use crate::chip9::FaultContext;
+
 #[derive(Debug)]
 pub enum Chip9Error {
     FileReadError(String),
@@ -8,6 +10,19 @@ pub enum Chip9Error {
     UnrecognizedOpcode(u16),
     WindowCreationError(minifb::Error),
     WindowUpdateError(minifb::Error),
+    StateIoError(std::io::Error),
+    InvalidState(String),
+    UnsupportedStateVersion(u16, u16),
+    AssemblyError(usize, String),
+    StackOverflow(FaultContext),
+    StackUnderflow(FaultContext),
+    PcOutOfRange(FaultContext),
+    IndexOutOfRange(FaultContext),
+    TraceIoError(std::io::Error),
+    ConfigError(String),
+    TerminalError(std::io::Error),
+    ScreenshotError(std::io::Error),
+    RecordingError(gif::EncodingError),
 }
 
 impl fmt::Display for Chip9Error {
@@ -19,6 +34,19 @@ impl fmt::Display for Chip9Error {
             Chip9Error::UnrecognizedOpcode(op) => write!(f, "Unrecognized opcode: {:#X}", op),
             Chip9Error::WindowCreationError(e) => write!(f, "Window creation error: {}", e),
             Chip9Error::WindowUpdateError(e) => write!(f, "Window update error: {}", e),
+            Chip9Error::StateIoError(e) => write!(f, "Save state I/O error: {}", e),
+            Chip9Error::InvalidState(reason) => write!(f, "Invalid save state: {}", reason),
+            Chip9Error::UnsupportedStateVersion(found, expected) => write!(f, "Unsupported save state version: {}. Expected version {}.", found, expected),
+            Chip9Error::AssemblyError(line, reason) => write!(f, "Assembly error on line {}: {}", line, reason),
+            Chip9Error::StackOverflow(context) => write!(f, "Stack overflow: {}", context),
+            Chip9Error::StackUnderflow(context) => write!(f, "Stack underflow: {}", context),
+            Chip9Error::PcOutOfRange(context) => write!(f, "Program counter out of memory: {}", context),
+            Chip9Error::IndexOutOfRange(context) => write!(f, "Index register out of memory: {}", context),
+            Chip9Error::TraceIoError(e) => write!(f, "Trace I/O error: {}", e),
+            Chip9Error::ConfigError(reason) => write!(f, "Invalid config: {}", reason),
+            Chip9Error::TerminalError(e) => write!(f, "Terminal I/O error: {}", e),
+            Chip9Error::ScreenshotError(e) => write!(f, "Screenshot I/O error: {}", e),
+            Chip9Error::RecordingError(e) => write!(f, "Recording error: {}", e),
         }
     }
 }
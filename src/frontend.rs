@@ -0,0 +1,81 @@
+pub mod headless;
+#[cfg(test)]
+pub mod mock;
+pub mod terminal;
+pub mod window;
+
+use crate::audio::AudioSink;
+use crate::errors::Chip9Error;
+use crate::palette::Color;
+
+pub use headless::{HeadlessFrontend, KeyPress};
+#[cfg(test)]
+pub use mock::MockFrontend;
+pub use terminal::{Glyphs, TerminalFrontend};
+pub use window::MinifbFrontend;
+
+/// Where the emulator shows its display, reads the keys and plays the beep.
+/// The run loop in `Emulator` is written against this
+pub trait Frontend {
+    /// Shows a frame of the display
+    fn present(&mut self, frame: &Frame) -> Result<(), Chip9Error>;
+
+    /// Keys held and hotkeys pressed since the last call, called once per loop
+    fn poll_input(&mut self) -> Result<Input, Chip9Error>;
+
+    /// Sink the beep is played on while the sound timer runs
+    fn audio(&mut self) -> &mut dyn AudioSink;
+
+    /// Shows what the emulator is doing, e.g. "paused". Empty while running normally
+    fn set_status(&mut self, _status: &str) {}
+
+    /// Should frames be paced to 60 per second? Frontends without a user run as fast as they can
+    fn is_realtime(&self) -> bool {
+        true
+    }
+}
+
+/// Hotkeys, handled by the run loop
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    Pause,
+    /// Runs one frame while paused
+    FrameStep,
+    /// Runs one instruction while paused
+    InstructionStep,
+    Faster,
+    Slower,
+    CycleTheme,
+    ToggleMute,
+    SaveState(usize),
+    LoadState(usize),
+    Screenshot,
+    /// Starts or stops recording a GIF
+    ToggleRecording,
+}
+
+/// What the user did since the last poll
+#[derive(Clone, Debug, Default)]
+pub struct Input {
+    /// CHIP-8 keys held, bit n set for key n
+    pub keys: u16,
+    pub commands: Vec<Command>,
+    /// Is the rewind key held?
+    pub rewinding: bool,
+    pub quit: bool,
+}
+
+/// A display converted to colors, with the palette and pixel persistence applied
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Frame {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<Color>, // row by row
+    pub background: Color,
+}
+
+impl Frame {
+    pub fn pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[x + y * self.width]
+    }
+}
@@ -0,0 +1,81 @@
+use std::str::FromStr;
+
+use crate::audio::{AudioSink, Beeper};
+use crate::errors::Chip9Error;
+use crate::frontend::{Frame, Frontend, Input};
+
+/// CHIP-8 key held down for `frames` frames starting at `frame`,
+/// written as `FRAME:KEY[:FRAMES]` with the key in hex
+#[derive(Clone, Copy, Debug)]
+pub struct KeyPress {
+    pub frame: u64,
+    pub key: u8,
+    pub frames: u64,
+}
+
+impl KeyPress {
+    fn is_held(&self, frame: u64) -> bool {
+        frame >= self.frame && frame - self.frame < self.frames
+    }
+}
+
+impl FromStr for KeyPress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts: Vec<&str> = s.split(':').collect();
+        let (frame, key, frames) = match parts[..] {
+            [frame, key] => (frame, key, "1"),
+            [frame, key, frames] => (frame, key, frames),
+            _ => return Err(format!("expected FRAME:KEY[:FRAMES], found '{}'", s)),
+        };
+
+        let frame = frame.parse().map_err(|_| format!("invalid frame '{}'", frame))?;
+        let key = u8::from_str_radix(key, 16).ok()
+            .filter(|&key| key < 16)
+            .ok_or_else(|| format!("invalid key '{}', expected 0-F", key))?;
+        let frames = frames.parse().map_err(|_| format!("invalid frame count '{}'", frames))?;
+        Ok(Self { frame, key, frames })
+    }
+}
+
+/// Runs for a fixed number of frames without a window or audio, with scripted keys
+pub struct HeadlessFrontend {
+    frames: u64,
+    frame: u64, // frames polled so far
+    script: Vec<KeyPress>,
+    beeper: Beeper,
+}
+
+impl HeadlessFrontend {
+    pub fn new(frames: u64) -> Self {
+        Self { frames, frame: 0, script: Vec::new(), beeper: Beeper::silent() }
+    }
+
+    pub fn set_script(&mut self, script: Vec<KeyPress>) {
+        self.script = script;
+    }
+}
+
+impl Frontend for HeadlessFrontend {
+    fn present(&mut self, _frame: &Frame) -> Result<(), Chip9Error> {
+        Ok(())
+    }
+
+    fn poll_input(&mut self) -> Result<Input, Chip9Error> {
+        let frame = self.frame;
+        self.frame += 1;
+        let keys = self.script.iter()
+            .filter(|press| press.is_held(frame))
+            .fold(0, |keys, press| keys | (1 << press.key));
+        Ok(Input { keys, quit: frame >= self.frames, ..Input::default() })
+    }
+
+    fn audio(&mut self) -> &mut dyn AudioSink {
+        &mut self.beeper
+    }
+
+    fn is_realtime(&self) -> bool {
+        false
+    }
+}
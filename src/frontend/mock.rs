@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+
+use crate::audio::{AudioPattern, AudioSink};
+use crate::errors::Chip9Error;
+use crate::frontend::{Frame, Frontend, Input};
+
+/// Replays scripted input and records everything the run loop outputs,
+/// for testing the loop without a user
+#[derive(Default)]
+pub struct MockFrontend {
+    inputs: VecDeque<Input>, // one per poll, quits once they run out
+    pub frames: Vec<Frame>,
+    pub statuses: Vec<String>,
+    pub audio: MockAudio,
+}
+
+/// Records the beeper state on each frame
+#[derive(Default)]
+pub struct MockAudio {
+    pub playing: Vec<bool>,
+    pub muted: bool,
+    pub pattern: Option<AudioPattern>, // last one set
+}
+
+impl AudioSink for MockAudio {
+    fn set_playing(&mut self, playing: bool) {
+        self.playing.push(playing);
+    }
+
+    fn toggle_mute(&mut self) {
+        self.muted = !self.muted;
+    }
+
+    fn set_pattern(&mut self, pattern: Option<AudioPattern>) {
+        self.pattern = pattern;
+    }
+}
+
+impl MockFrontend {
+    pub fn new(inputs: impl IntoIterator<Item = Input>) -> Self {
+        Self { inputs: inputs.into_iter().collect(), ..Self::default() }
+    }
+}
+
+impl Frontend for MockFrontend {
+    fn present(&mut self, frame: &Frame) -> Result<(), Chip9Error> {
+        self.frames.push(frame.clone());
+        Ok(())
+    }
+
+    fn poll_input(&mut self) -> Result<Input, Chip9Error> {
+        Ok(self.inputs.pop_front().unwrap_or(Input { quit: true, ..Input::default() }))
+    }
+
+    fn audio(&mut self) -> &mut dyn AudioSink {
+        &mut self.audio
+    }
+
+    fn set_status(&mut self, status: &str) {
+        self.statuses.push(status.to_string());
+    }
+
+    fn is_realtime(&self) -> bool {
+        false
+    }
+}
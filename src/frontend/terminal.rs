@@ -0,0 +1,337 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::io::{self, BufWriter, Stdout, Write};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyEventState, KeyModifiers};
+use crossterm::event::{KeyboardEnhancementFlags, PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags};
+use crossterm::style::{self, Print, ResetColor, SetBackgroundColor, SetForegroundColor};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use crate::audio::{AudioConfig, AudioSink, Beeper};
+use crate::bindings::Bindings;
+use crate::errors::Chip9Error;
+use crate::frontend::{Command, Frame, Frontend, Input};
+use crate::palette::Color;
+
+// Terminals without key release events only repeat held keys, so a press counts for this many frames
+const HOLD_FRAMES: u32 = 8;
+const REWIND_KEY: &str = "Backspace";
+const COMMAND_KEYS: [(KeyCode, Command); 9] = [
+    (KeyCode::Char('m'), Command::ToggleMute),
+    (KeyCode::Char('p'), Command::Pause),
+    (KeyCode::Char('.'), Command::FrameStep),
+    (KeyCode::Char(','), Command::InstructionStep),
+    (KeyCode::Char('='), Command::Faster),
+    (KeyCode::Char('-'), Command::Slower),
+    (KeyCode::Char('t'), Command::CycleTheme),
+    (KeyCode::F(11), Command::ToggleRecording),
+    (KeyCode::F(12), Command::Screenshot),
+];
+
+/// Characters the display is drawn with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Glyphs {
+    /// ▀ with separate top and bottom colors, 1x2 pixels per character
+    #[default]
+    HalfBlock,
+    /// Braille dots, 2x4 pixels per character in a single color
+    Braille,
+}
+
+impl Glyphs {
+    pub const ALL: [Glyphs; 2] = [Glyphs::HalfBlock, Glyphs::Braille];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Glyphs::HalfBlock => "half-block",
+            Glyphs::Braille => "braille",
+        }
+    }
+
+    // Pixels per character
+    fn cell_size(&self) -> (usize, usize) {
+        match self {
+            Glyphs::HalfBlock => (1, 2),
+            Glyphs::Braille => (2, 4),
+        }
+    }
+
+    // Character for the pixels starting at `x`, `y`
+    fn cell(&self, frame: &Frame, x: usize, y: usize) -> Cell {
+        match self {
+            Glyphs::HalfBlock => Cell {
+                glyph: '▀',
+                fg: frame.pixel(x, y),
+                bg: frame.pixel(x, y + 1),
+            },
+            Glyphs::Braille => {
+                // Dot bits of a braille character, by column then row
+                const DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+                let mut dots = 0;
+                let mut fg = frame.background;
+                for (i, column) in DOTS.iter().enumerate() {
+                    for (j, &dot) in column.iter().enumerate() {
+                        let color = frame.pixel(x + i, y + j);
+                        if color != frame.background {
+                            dots |= dot;
+                            fg = color;
+                        }
+                    }
+                }
+                Cell { glyph: char::from_u32(0x2800 + dots).unwrap(), fg, bg: frame.background }
+            }
+        }
+    }
+}
+
+impl fmt::Display for Glyphs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Glyphs {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Glyphs::ALL.into_iter()
+            .find(|glyphs| glyphs.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Glyphs::ALL.iter().map(Glyphs::name).collect();
+                format!("unknown glyphs '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Cell {
+    glyph: char,
+    fg: Color,
+    bg: Color,
+}
+
+/// Draws the display in the terminal, for sessions without a display server.
+/// Escape or Ctrl+C quits
+pub struct TerminalFrontend {
+    out: BufWriter<Stdout>,
+    glyphs: Glyphs,
+    bindings: Bindings,
+    beeper: Beeper,
+    cells: Vec<Cell>, // on screen, empty when everything needs redrawing
+    last_frame: Option<Frame>, // redrawn when the terminal is resized
+    held: HashMap<&'static str, u32>, // host keys and the frames they're held for
+    screen: Screen,
+}
+
+impl TerminalFrontend {
+    /// Switches the terminal to raw mode on the alternate screen until dropped
+    pub fn new(glyphs: Glyphs, bindings: Bindings, audio: AudioConfig) -> Result<Self, Chip9Error> {
+        let mut out = BufWriter::new(io::stdout());
+        let releases = terminal::supports_keyboard_enhancement().unwrap_or(false);
+        let screen = Screen::enter(&mut out, releases).map_err(Chip9Error::TerminalError)?;
+
+        Ok(Self {
+            out,
+            glyphs,
+            bindings,
+            beeper: Beeper::new(audio),
+            cells: Vec::new(),
+            last_frame: None,
+            held: HashMap::new(),
+            screen,
+        })
+    }
+
+    fn render(&mut self, frame: &Frame) -> io::Result<()> {
+        let out = &mut self.out;
+        let (cell_width, cell_height) = self.glyphs.cell_size();
+        let (columns, rows) = (frame.width / cell_width, frame.height / cell_height);
+        if self.cells.len() != columns * rows {
+            self.cells.clear();
+            queue!(out, ResetColor, terminal::Clear(terminal::ClearType::All))?;
+        }
+        let redraw = self.cells.is_empty();
+        self.cells.resize(columns * rows, Cell { glyph: ' ', fg: frame.background, bg: frame.background });
+
+        // Centered, clipped if the terminal is too small
+        let (width, height) = terminal::size()?;
+        let left = (width as usize).saturating_sub(columns) / 2;
+        let top = (height as usize).saturating_sub(rows) / 2;
+
+        // Cursor and colors are only sent when they differ from what the last character left
+        let mut cursor = None;
+        let mut colors = None;
+        for row in 0..rows.min(height as usize) {
+            for column in 0..columns.min(width as usize) {
+                let cell = self.glyphs.cell(frame, column * cell_width, row * cell_height);
+                let drawn = &mut self.cells[column + row * columns];
+                if *drawn == cell && !redraw {
+                    continue;
+                }
+                *drawn = cell;
+
+                let position = ((left + column) as u16, (top + row) as u16);
+                if cursor != Some(position) {
+                    queue!(out, MoveTo(position.0, position.1))?;
+                }
+                if colors != Some((cell.fg, cell.bg)) {
+                    queue!(out, SetForegroundColor(ansi(cell.fg)), SetBackgroundColor(ansi(cell.bg)))?;
+                }
+                queue!(out, Print(cell.glyph))?;
+                cursor = Some((position.0 + 1, position.1));
+                colors = Some((cell.fg, cell.bg));
+            }
+        }
+        out.flush()
+    }
+}
+
+impl Frontend for TerminalFrontend {
+    fn present(&mut self, frame: &Frame) -> Result<(), Chip9Error> {
+        self.render(frame).map_err(Chip9Error::TerminalError)?;
+        self.last_frame = Some(frame.clone());
+        Ok(())
+    }
+
+    fn poll_input(&mut self) -> Result<Input, Chip9Error> {
+        let mut input = Input::default();
+        self.held.retain(|_, frames| {
+            *frames = frames.saturating_sub(1);
+            *frames > 0
+        });
+
+        while event::poll(Duration::ZERO).map_err(Chip9Error::TerminalError)? {
+            match event::read().map_err(Chip9Error::TerminalError)? {
+                Event::Key(KeyEvent { code: KeyCode::Esc, .. }) => input.quit = true,
+                Event::Key(KeyEvent { code: KeyCode::Char('c'), modifiers, .. }) if modifiers.contains(KeyModifiers::CONTROL) => input.quit = true,
+                Event::Key(key) => self.update_key(&key, &mut input.commands),
+                Event::Resize(..) => {
+                    self.cells.clear();
+                    if let Some(frame) = self.last_frame.take() {
+                        self.present(&frame)?;
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        input.keys = self.held.keys()
+            .filter_map(|name| self.bindings.get(name))
+            .fold(0, |keys, key| keys | (1 << key));
+        input.rewinding = self.held.contains_key(REWIND_KEY);
+        Ok(input)
+    }
+
+    fn audio(&mut self) -> &mut dyn AudioSink {
+        &mut self.beeper
+    }
+}
+
+impl TerminalFrontend {
+    fn update_key(&mut self, key: &KeyEvent, commands: &mut Vec<Command>) {
+        if key.kind == KeyEventKind::Press || (key.kind == KeyEventKind::Repeat && matches!(key.code, KeyCode::Char('.' | ','))) {
+            // F1-F10 load the state slots 0-9, with Shift held they save them
+            match key.code {
+                KeyCode::F(n @ 1..=10) if key.modifiers.contains(KeyModifiers::SHIFT) => commands.push(Command::SaveState(n as usize - 1)),
+                KeyCode::F(n @ 1..=10) => commands.push(Command::LoadState(n as usize - 1)),
+                code => commands.extend(COMMAND_KEYS.iter()
+                    .filter(|(command_key, _)| *command_key == code)
+                    .map(|&(_, command)| command)),
+            }
+        }
+
+        let Some(name) = host_key_name(key) else {
+            return;
+        };
+        match key.kind {
+            KeyEventKind::Release => { self.held.remove(name); }
+            _ if self.screen.enhanced => { self.held.insert(name, u32::MAX); }
+            _ => { self.held.insert(name, HOLD_FRAMES); }
+        }
+    }
+}
+
+// Raw mode on the alternate screen, restored when dropped
+struct Screen {
+    enhanced: bool,
+}
+
+impl Screen {
+    fn enter(out: &mut BufWriter<Stdout>, enhanced: bool) -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(out, EnterAlternateScreen, Hide)?;
+        if enhanced {
+            execute!(out, PushKeyboardEnhancementFlags(
+                KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES | KeyboardEnhancementFlags::REPORT_EVENT_TYPES,
+            ))?;
+        }
+        Ok(Self { enhanced })
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let mut out = io::stdout();
+        if self.enhanced {
+            let _ = execute!(out, PopKeyboardEnhancementFlags);
+        }
+        let _ = execute!(out, ResetColor, Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn ansi(color: Color) -> style::Color {
+    let [r, g, b] = color.rgb();
+    style::Color::Rgb { r, g, b }
+}
+
+// Name of a key in `bindings::KEY_NAMES`
+fn host_key_name(key: &KeyEvent) -> Option<&'static str> {
+    const DIGITS: [&str; 10] = ["0", "1", "2", "3", "4", "5", "6", "7", "8", "9"];
+    const KEYPAD_DIGITS: [&str; 10] = [
+        "NumPad0", "NumPad1", "NumPad2", "NumPad3", "NumPad4",
+        "NumPad5", "NumPad6", "NumPad7", "NumPad8", "NumPad9",
+    ];
+    const LETTERS: [&str; 26] = [
+        "A", "B", "C", "D", "E", "F", "G", "H", "I", "J", "K", "L", "M",
+        "N", "O", "P", "Q", "R", "S", "T", "U", "V", "W", "X", "Y", "Z",
+    ];
+
+    let keypad = key.state.contains(KeyEventState::KEYPAD);
+    let name = match key.code {
+        KeyCode::Char(c @ '0'..='9') if keypad => KEYPAD_DIGITS[c as usize - '0' as usize],
+        KeyCode::Char(c @ '0'..='9') => DIGITS[c as usize - '0' as usize],
+        KeyCode::Char(c) if c.is_ascii_alphabetic() => LETTERS[(c.to_ascii_uppercase() as u8 - b'A') as usize],
+        KeyCode::Char('.') if keypad => "NumPadDot",
+        KeyCode::Char('/') if keypad => "NumPadSlash",
+        KeyCode::Char('*') if keypad => "NumPadAsterisk",
+        KeyCode::Char('-') if keypad => "NumPadMinus",
+        KeyCode::Char('+') if keypad => "NumPadPlus",
+        KeyCode::Enter if keypad => "NumPadEnter",
+        KeyCode::Char(' ') => "Space",
+        KeyCode::Char('\'') => "Apostrophe",
+        KeyCode::Char('`') => "Backquote",
+        KeyCode::Char('\\') => "Backslash",
+        KeyCode::Char(',') => "Comma",
+        KeyCode::Char('=') => "Equal",
+        KeyCode::Char('[') => "LeftBracket",
+        KeyCode::Char('-') => "Minus",
+        KeyCode::Char('.') => "Period",
+        KeyCode::Char(']') => "RightBracket",
+        KeyCode::Char(';') => "Semicolon",
+        KeyCode::Char('/') => "Slash",
+        KeyCode::Up => "Up",
+        KeyCode::Down => "Down",
+        KeyCode::Left => "Left",
+        KeyCode::Right => "Right",
+        KeyCode::Enter => "Enter",
+        KeyCode::Tab => "Tab",
+        KeyCode::Backspace => REWIND_KEY,
+        _ => return None,
+    };
+    Some(name)
+}
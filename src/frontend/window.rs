@@ -0,0 +1,121 @@
+use minifb::{Key, KeyRepeat};
+use minifb::{Window, WindowOptions, ScaleMode, Scale};
+
+use crate::audio::{AudioConfig, AudioSink, Beeper};
+use crate::bindings::Bindings;
+use crate::chip9::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+use crate::errors::Chip9Error;
+use crate::frontend::{Command, Frame, Frontend, Input};
+
+const WINDOW_NAME: &str = "Chip9";
+const REWIND_KEY: Key = Key::Backspace;
+const COMMAND_KEYS: [(Key, KeyRepeat, Command); 9] = [
+    (Key::M, KeyRepeat::No, Command::ToggleMute),
+    (Key::P, KeyRepeat::No, Command::Pause),
+    (Key::Period, KeyRepeat::Yes, Command::FrameStep),
+    (Key::Comma, KeyRepeat::Yes, Command::InstructionStep),
+    (Key::Equal, KeyRepeat::No, Command::Faster),
+    (Key::Minus, KeyRepeat::No, Command::Slower),
+    (Key::T, KeyRepeat::No, Command::CycleTheme),
+    (Key::F11, KeyRepeat::No, Command::ToggleRecording),
+    (Key::F12, KeyRepeat::No, Command::Screenshot),
+];
+// F1-F10 load the state slots 0-9, with Shift held they save them
+const STATE_KEYS: [Key; 10] = [
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5,
+    Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+];
+
+/// Shows the display in a window
+pub struct MinifbFrontend {
+    window: Window,
+    buffer: Vec<u32>,
+    bindings: Bindings,
+    beeper: Beeper,
+    presented: bool, // since the last poll, presenting also handles window events
+}
+
+impl MinifbFrontend {
+    pub fn new(bindings: Bindings, audio: AudioConfig) -> Result<Self, Chip9Error> {
+        let mut window = Window::new(
+            WINDOW_NAME,
+            DISPLAY_WIDTH,
+            DISPLAY_HEIGHT,
+            WindowOptions {
+                resize: true,
+                scale: Scale::X8,
+                scale_mode: ScaleMode::AspectRatioStretch,
+                ..WindowOptions::default()
+            },
+        )
+        .map_err(Chip9Error::WindowCreationError)?;
+        window.set_target_fps(0); // frames are paced by the run loop
+
+        Ok(Self {
+            window,
+            buffer: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            bindings,
+            beeper: Beeper::new(audio),
+            presented: false,
+        })
+    }
+}
+
+impl Frontend for MinifbFrontend {
+    fn present(&mut self, frame: &Frame) -> Result<(), Chip9Error> {
+        for (pixel, color) in self.buffer.iter_mut().zip(&frame.pixels) {
+            *pixel = color.value();
+        }
+        self.presented = true;
+        self.window
+            .update_with_buffer(&self.buffer[..frame.width * frame.height], frame.width, frame.height)
+            .map_err(Chip9Error::WindowUpdateError)
+    }
+
+    fn poll_input(&mut self) -> Result<Input, Chip9Error> {
+        if !self.presented {
+            self.window.update();
+        }
+        self.presented = false;
+
+        let window = &self.window;
+        let keys = window.get_keys()
+            .into_iter()
+            .filter_map(|key| self.bindings.get(&host_key_name(key)))
+            .fold(0, |keys, key| keys | (1 << key));
+
+        let mut commands: Vec<Command> = COMMAND_KEYS.iter()
+            .filter(|&&(key, repeat, _)| window.is_key_pressed(key, repeat))
+            .map(|&(_, _, command)| command)
+            .collect();
+        if let Some(slot) = STATE_KEYS.iter().position(|&key| window.is_key_pressed(key, KeyRepeat::No)) {
+            let saving = window.is_key_down(Key::LeftShift) || window.is_key_down(Key::RightShift);
+            commands.push(if saving { Command::SaveState(slot) } else { Command::LoadState(slot) });
+        }
+
+        Ok(Input {
+            keys,
+            commands,
+            rewinding: window.is_key_down(REWIND_KEY),
+            quit: !window.is_open(),
+        })
+    }
+
+    fn audio(&mut self) -> &mut dyn AudioSink {
+        &mut self.beeper
+    }
+
+    fn set_status(&mut self, status: &str) {
+        let title = match status {
+            "" => WINDOW_NAME.to_string(),
+            status => format!("{} - {}", WINDOW_NAME, status),
+        };
+        self.window.set_title(&title);
+    }
+}
+
+// Name of a minifb key in `bindings::KEY_NAMES`, which match the variant names except for the digits
+fn host_key_name(key: Key) -> String {
+    let name = format!("{:?}", key);
+    name.strip_prefix("Key").map(str::to_string).unwrap_or(name)
+}
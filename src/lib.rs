@@ -1,6 +1,18 @@
 pub mod chip9;
 pub mod errors;
 pub mod app;
+pub mod audio;
+pub mod debugger;
+pub mod disasm;
+pub mod asm;
+pub mod screenshot;
+pub mod recording;
+pub mod rewind;
+pub mod bindings;
+pub mod config;
+pub mod palette;
+pub mod persistence;
+pub mod frontend;
 
 pub use chip9::Chip9;
 pub use app::Emulator;
\ No newline at end of file
@@ -1,25 +1,309 @@
 use chip9::Chip9;
 use chip9::Emulator;
-use std::fs::File;
-use clap::Parser;
+use chip9::chip9::{diff_traces, FaultAction, FaultPolicy, PcRange, Preset, Trace, TraceFilter, DEFAULT_IPF};
+use chip9::audio::{AudioConfig, Waveform};
+use chip9::debugger::Debugger;
+use chip9::disasm::Disassembler;
+use chip9::asm;
+use chip9::config::{Config, Settings};
+use chip9::palette::{Color, Theme};
+use chip9::persistence::Persistence;
+use chip9::frontend::{Frontend, Glyphs, HeadlessFrontend, KeyPress, MinifbFrontend, TerminalFrontend};
+use chip9::rewind::DEFAULT_REWIND_SECONDS;
+use chip9::screenshot::{self, DEFAULT_SCREENSHOT_SCALE};
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::process::ExitCode;
+use clap::{Args, Parser, Subcommand};
 use std::path::PathBuf;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
-struct Args {
-    path: PathBuf
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-fn main() {
-    let args = Args::parse();
-    let program = &File::open(args.path).unwrap();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a ROM in a window
+    Run(RunArgs),
+    /// Step through a ROM in an interactive command-line debugger
+    Debug(MachineArgs),
+    /// Print a disassembly of a ROM
+    Disasm(DisasmArgs),
+    /// Assemble Octo source into a ROM
+    Asm(AsmArgs),
+    /// Compare two execution traces and report the first divergence
+    TraceDiff(TraceDiffArgs),
+}
 
-    let mut chip9 = Chip9::new();
-    chip9.load_program(program).unwrap();
+#[derive(Args, Debug)]
+struct MachineArgs {
+    path: PathBuf,
 
-    let mut app = Emulator::new();
+    /// Config file with key bindings, colors, speed and quirks. Defaults to chip9.toml if it exists
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Quirks preset matching the interpreter the ROM was written for [default: vip]
+    #[arg(long)]
+    quirks: Option<Preset>,
+
+    /// Seed for the random number generator, makes runs reproducible
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// What to do on a call with a full stack: fault, wrap or ignore
+    #[arg(long, default_value_t = FaultAction::Fault)]
+    on_stack_overflow: FaultAction,
+
+    /// What to do on a return with an empty stack: fault, wrap or ignore
+    #[arg(long, default_value_t = FaultAction::Fault)]
+    on_stack_underflow: FaultAction,
+
+    /// What to do when PC runs past the end of memory: fault, wrap or ignore
+    #[arg(long, default_value_t = FaultAction::Fault)]
+    on_pc_out_of_range: FaultAction,
+
+    /// What to do when I points past the end of memory: fault, wrap or ignore
+    #[arg(long, default_value_t = FaultAction::Fault)]
+    on_index_out_of_range: FaultAction,
+
+    /// Log every executed instruction with the machine state to a file
+    #[arg(long)]
+    trace: Option<PathBuf>,
+
+    /// Only trace instructions with PC in this range, as START-END in hex
+    #[arg(long, value_name = "START-END", requires = "trace")]
+    trace_range: Option<PcRange>,
+
+    /// Start tracing after this many instructions
+    #[arg(long, default_value_t = 0, requires = "trace")]
+    trace_skip: u64,
+
+    /// Stop tracing after this many lines
+    #[arg(long, requires = "trace")]
+    trace_limit: Option<u64>,
+}
+
+#[derive(Args, Debug)]
+struct DisasmArgs {
+    path: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct TraceDiffArgs {
+    a: PathBuf,
+    b: PathBuf,
+}
+
+#[derive(Args, Debug)]
+struct AsmArgs {
+    path: PathBuf,
+
+    /// Output ROM, defaults to the source path with a .ch8 extension. Symbols are written next to it as .sym
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args, Debug)]
+struct RunArgs {
+    #[command(flatten)]
+    machine: MachineArgs,
+
+    /// Instructions executed per 60Hz frame [default: 11]
+    #[arg(long)]
+    ipf: Option<u32>,
+
+    /// Built-in color theme: mono, green, amber, lcd, octo or high-contrast
+    #[arg(long)]
+    theme: Option<Theme>,
+
+    /// Foreground color as #RRGGBB, replaces the theme's
+    #[arg(long, value_name = "#RRGGBB")]
+    fg: Option<Color>,
+
+    /// Background color as #RRGGBB, replaces the theme's
+    #[arg(long, value_name = "#RRGGBB")]
+    bg: Option<Color>,
+
+    /// How long pixels fade after turning off, hides flicker: off, light or heavy [default: off]
+    #[arg(long)]
+    persistence: Option<Persistence>,
+
+    /// Seconds of gameplay kept for rewinding, 0 disables it
+    #[arg(long, default_value_t = DEFAULT_REWIND_SECONDS)]
+    rewind: u32,
+
+    /// Waveform of the beeper
+    #[arg(long, default_value_t = Waveform::Square)]
+    waveform: Waveform,
+
+    /// Beeper frequency in Hz
+    #[arg(long, default_value_t = 440.0)]
+    frequency: f32,
+
+    /// Beeper volume, from 0.0 to 1.0
+    #[arg(long, default_value_t = 0.25)]
+    volume: f32,
+
+    /// Run without a window or audio, for scripted testing
+    #[arg(long, conflicts_with = "terminal")]
+    headless: bool,
+
+    /// Draw in the terminal instead of a window, e.g. over SSH
+    #[arg(long)]
+    terminal: bool,
+
+    /// Characters the terminal draws with: half-block or braille
+    #[arg(long, default_value_t = Glyphs::HalfBlock, requires = "terminal")]
+    glyphs: Glyphs,
+
+    /// Frames to run in headless mode, at 60 frames per second
+    #[arg(long, default_value_t = 600, requires = "headless")]
+    frames: u64,
+
+    /// Key held in headless mode, as FRAME:KEY[:FRAMES] with the key in hex. Can be repeated
+    #[arg(long = "key", value_name = "FRAME:KEY[:FRAMES]", requires = "headless")]
+    keys: Vec<KeyPress>,
 
-    if let Err(e) = app.run(chip9) {
+    /// Where to write the final display in headless mode: .png, .pgm, .pbm or ASCII art otherwise. Defaults to ASCII on stdout
+    #[arg(long, requires = "headless")]
+    dump: Option<PathBuf>,
+
+    /// Record the run into an animated GIF, F11 starts and stops recording while running
+    #[arg(long, value_name = "PATH")]
+    record: Option<PathBuf>,
+
+    /// Size of a CHIP-8 pixel in screenshots, recordings and dumped images [default: 4, 1 for --dump]
+    #[arg(long)]
+    scale: Option<usize>,
+}
+
+fn main() -> ExitCode {
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Run(args) => run(args),
+        Command::Debug(args) => debug(args),
+        Command::Disasm(args) => disasm(args),
+        Command::Asm(args) => assemble(args),
+        Command::TraceDiff(args) => trace_diff(args),
+    };
+
+    if let Err(e) = result {
         eprintln!("Error while running chip9: {e}");
+        return ExitCode::FAILURE;
+    }
+    ExitCode::SUCCESS
+}
+
+// Config file settings for the ROM, command line flags take precedence over them
+fn settings(args: &MachineArgs) -> Result<Settings, Box<dyn Error>> {
+    Ok(Config::find(args.config.as_deref())?.settings(&args.path)?)
+}
+
+fn load(args: &MachineArgs, settings: &Settings) -> Result<Chip9, Box<dyn Error>> {
+    let program = &File::open(&args.path)?;
+
+    let quirks = args.quirks.or(settings.quirks).unwrap_or_default();
+    let mut chip9 = Chip9::new(quirks.quirks());
+    chip9.set_fault_policy(FaultPolicy {
+        stack_overflow: args.on_stack_overflow,
+        stack_underflow: args.on_stack_underflow,
+        pc_out_of_range: args.on_pc_out_of_range,
+        index_out_of_range: args.on_index_out_of_range,
+    });
+    if let Some(seed) = args.seed {
+        chip9.set_seed(seed);
+    }
+    if let Some(path) = &args.trace {
+        let filter = TraceFilter {
+            pc_range: args.trace_range.clone().map_or(0..=u16::MAX, |range| range.0),
+            skip: args.trace_skip,
+            limit: args.trace_limit,
+        };
+        chip9.set_trace(Trace::new(Box::new(BufWriter::new(File::create(path)?)), filter));
+    }
+    chip9.load_program(program)?;
+    Ok(chip9)
+}
+
+fn run(args: RunArgs) -> Result<(), Box<dyn Error>> {
+    let settings = settings(&args.machine)?;
+    let mut chip9 = load(&args.machine, &settings)?;
+
+    let mut app = Emulator::new();
+    app.set_instructions_per_frame(args.ipf.or(settings.ipf).unwrap_or(DEFAULT_IPF));
+    if let Some(theme) = args.theme.or(settings.theme) {
+        app.set_theme(theme);
+    }
+    let palette = args.theme.map(|theme| theme.palette()).or(settings.palette).unwrap_or_default().with(args.bg, args.fg);
+    app.set_palette(palette);
+    app.set_persistence(args.persistence.or(settings.persistence).unwrap_or_default());
+    app.set_state_path(&args.machine.path);
+    app.set_capture_path(&args.machine.path);
+    app.set_capture_scale(args.scale.unwrap_or(DEFAULT_SCREENSHOT_SCALE));
+    app.set_rewind_seconds(if args.headless { 0 } else { args.rewind });
+    if let Some(path) = &args.record {
+        app.start_recording(path)?;
+    }
+
+    let audio = AudioConfig { waveform: args.waveform, frequency: args.frequency, volume: args.volume };
+    let mut frontend: Box<dyn Frontend> = if args.headless {
+        let mut headless = HeadlessFrontend::new(args.frames);
+        headless.set_script(args.keys);
+        Box::new(headless)
+    } else if args.terminal {
+        Box::new(TerminalFrontend::new(args.glyphs, settings.bindings, audio)?)
+    } else {
+        Box::new(MinifbFrontend::new(settings.bindings, audio)?)
+    };
+    app.run(&mut chip9, frontend.as_mut())?;
+    drop(frontend);
+
+    if args.headless {
+        match &args.dump {
+            Some(path) => chip9.save_screenshot(path, &palette, args.scale.unwrap_or(1))?,
+            None => screenshot::write_ascii(&chip9.display, io::stdout().lock())?,
+        }
+    }
+    Ok(())
+}
+
+fn debug(args: MachineArgs) -> Result<(), Box<dyn Error>> {
+    let chip9 = load(&args, &settings(&args)?)?;
+
+    let mut debugger = Debugger::new(chip9);
+    debugger.run(io::stdin().lock(), io::stdout())?;
+    Ok(())
+}
+
+fn disasm(args: DisasmArgs) -> Result<(), Box<dyn Error>> {
+    let rom = fs::read(&args.path)?;
+
+    Disassembler::new(&rom).write(io::stdout().lock())?;
+    Ok(())
+}
+
+fn assemble(args: AsmArgs) -> Result<(), Box<dyn Error>> {
+    let source = fs::read_to_string(&args.path)?;
+    let program = asm::assemble(&source)?;
+
+    let output = args.output.unwrap_or_else(|| args.path.with_extension("ch8"));
+    fs::write(&output, &program.rom)?;
+    program.write_symbols(File::create(output.with_extension("sym"))?)?;
+    Ok(())
+}
+
+fn trace_diff(args: TraceDiffArgs) -> Result<(), Box<dyn Error>> {
+    let a = BufReader::new(File::open(&args.a)?);
+    let b = BufReader::new(File::open(&args.b)?);
+
+    match diff_traces(a, b, io::stdout().lock())? {
+        Some(line) => Err(format!("traces diverge at line {}", line).into()),
+        None => Ok(()),
     }
 }
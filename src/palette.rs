@@ -0,0 +1,223 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chip9::DISPLAY_PLANES;
+
+/// Colors in a palette, one per pixel value of the multi-plane display
+pub const PALETTE_SIZE: usize = 1 << DISPLAY_PLANES;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Color {
+    value: u32,
+}
+
+impl From<(u8, u8, u8)> for Color {
+    fn from((r, g, b): (u8, u8, u8)) -> Self {
+        Self { value: ((r as u32) << 16) | ((g as u32) << 8) | b as u32 }
+    }
+}
+
+impl Color {
+    const fn hex(value: u32) -> Self {
+        Self { value }
+    }
+
+    /// As 0x00RRGGBB
+    pub fn value(&self) -> u32 {
+        self.value
+    }
+
+    pub fn rgb(&self) -> [u8; 3] {
+        let [_, r, g, b] = self.value.to_be_bytes();
+        [r, g, b]
+    }
+
+    /// Blends towards `other`, by `amount` from 0.0 (self) to 1.0 (other)
+    pub fn mix(&self, other: Color, amount: f32) -> Color {
+        let [r, g, b] = [0, 1, 2].map(|i| {
+            let (from, to) = (self.rgb()[i] as f32, other.rgb()[i] as f32);
+            (from + (to - from) * amount).round() as u8
+        });
+        Color::from((r, g, b))
+    }
+}
+
+impl fmt::Display for Color {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "#{:06X}", self.value)
+    }
+}
+
+/// Hex RGB color, as `#RRGGBB` or `RRGGBB`
+impl FromStr for Color {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let digits = s.strip_prefix('#').unwrap_or(s);
+        if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(format!("invalid color '{}', expected #RRGGBB", s));
+        }
+        Ok(Self { value: u32::from_str_radix(digits, 16).unwrap() })
+    }
+}
+
+/// Colors of the 4 pixel values of the 2-plane display,
+/// in order: empty, plane 1, plane 2, both planes
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette(pub [Color; PALETTE_SIZE]);
+
+impl Default for Palette {
+    fn default() -> Self {
+        Theme::default().palette()
+    }
+}
+
+impl Palette {
+    /// Background and foreground, the other two colors are mixed from them
+    pub fn two_color(background: Color, foreground: Color) -> Self {
+        Self([
+            background,
+            foreground,
+            background.mix(foreground, 2.0 / 3.0),
+            background.mix(foreground, 1.0 / 3.0),
+        ])
+    }
+
+    /// Either all 4 colors, or the background and foreground
+    pub fn from_colors(colors: &[Color]) -> Result<Self, String> {
+        match *colors {
+            [background, foreground] => Ok(Self::two_color(background, foreground)),
+            [empty, plane1, plane2, both] => Ok(Self([empty, plane1, plane2, both])),
+            _ => Err(format!("expected 2 or {} colors, found {}", PALETTE_SIZE, colors.len())),
+        }
+    }
+
+    pub fn get(&self, pixel: u8) -> Color {
+        self.0[pixel as usize]
+    }
+
+    /// Replaces the background and foreground, keeping the other two colors
+    /// unless both were given, in which case they're mixed from them
+    pub fn with(self, background: Option<Color>, foreground: Option<Color>) -> Self {
+        match (background, foreground) {
+            (Some(background), Some(foreground)) => Self::two_color(background, foreground),
+            (background, foreground) => {
+                let mut colors = self.0;
+                colors[0] = background.unwrap_or(colors[0]);
+                colors[1] = foreground.unwrap_or(colors[1]);
+                Self(colors)
+            }
+        }
+    }
+}
+
+/// Built-in palettes
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Theme {
+    /// White on black
+    #[default]
+    Mono,
+    /// Green monochrome monitor
+    GreenPhosphor,
+    /// Amber monochrome monitor
+    Amber,
+    /// Greenish handheld LCD
+    Lcd,
+    /// Colors of the Octo IDE
+    Octo,
+    /// Saturated colors on black
+    HighContrast,
+}
+
+impl Theme {
+    pub const ALL: [Theme; 6] = [Theme::Mono, Theme::GreenPhosphor, Theme::Amber, Theme::Lcd, Theme::Octo, Theme::HighContrast];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Theme::Mono => "mono",
+            Theme::GreenPhosphor => "green",
+            Theme::Amber => "amber",
+            Theme::Lcd => "lcd",
+            Theme::Octo => "octo",
+            Theme::HighContrast => "high-contrast",
+        }
+    }
+
+    pub fn palette(&self) -> Palette {
+        let colors = match self {
+            Theme::Mono => [0x000000, 0xFFFFFF, 0xAAAAAA, 0x555555],
+            Theme::GreenPhosphor => [0x0A140A, 0x33FF66, 0x1FA043, 0x145A28],
+            Theme::Amber => [0x140C00, 0xFFB000, 0xB37700, 0x5C3D00],
+            Theme::Lcd => [0x9BBC0F, 0x0F380F, 0x306230, 0x8BAC0F],
+            Theme::Octo => [0x996600, 0xFFCC00, 0xFF6600, 0x662200],
+            Theme::HighContrast => [0x000000, 0xFFFFFF, 0xFFFF00, 0x00FFFF],
+        };
+        Palette(colors.map(Color::hex))
+    }
+
+    /// The theme after this one, wrapping around
+    pub fn next(self) -> Self {
+        let i = Self::ALL.iter().position(|&theme| theme == self).unwrap();
+        Self::ALL[(i + 1) % Self::ALL.len()]
+    }
+}
+
+impl fmt::Display for Theme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Theme {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Theme::ALL.into_iter()
+            .find(|theme| theme.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Theme::ALL.iter().map(Theme::name).collect();
+                format!("unknown theme '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: Color = Color::hex(0x000000);
+    const WHITE: Color = Color::hex(0xFFFFFF);
+    const RED: Color = Color::hex(0xFF0000);
+
+    #[test]
+    fn colors_parse_from_hex() {
+        assert_eq!("#12AB3f".parse(), Ok(Color::from((0x12, 0xAB, 0x3F))));
+        assert_eq!("12ab3f".parse(), Ok(Color::hex(0x12AB3F)));
+        for invalid in ["", "#", "#12345", "#1234567", "#12345G", "+12345", "##12345", "0x1234"] {
+            assert_eq!(invalid.parse::<Color>(), Err(format!("invalid color '{}', expected #RRGGBB", invalid)));
+        }
+    }
+
+    #[test]
+    fn from_colors_takes_two_or_four() {
+        assert_eq!(Palette::from_colors(&[BLACK, WHITE]), Ok(Palette::two_color(BLACK, WHITE)));
+        assert_eq!(Palette::from_colors(&[BLACK, WHITE, RED, BLACK]), Ok(Palette([BLACK, WHITE, RED, BLACK])));
+        assert_eq!(Palette::from_colors(&[BLACK, WHITE, RED]), Err("expected 2 or 4 colors, found 3".to_string()));
+        assert_eq!(Palette::from_colors(&[]), Err("expected 2 or 4 colors, found 0".to_string()));
+    }
+
+    #[test]
+    fn two_colors_mix_the_other_two() {
+        let palette = Palette::two_color(BLACK, WHITE);
+        assert_eq!(palette.0, [BLACK, WHITE, Color::hex(0xAAAAAA), Color::hex(0x555555)]);
+    }
+
+    #[test]
+    fn with_replaces_background_and_foreground() {
+        let [empty, plane1, plane2, both] = Theme::Octo.palette().0;
+        assert_eq!(Theme::Octo.palette().with(None, Some(RED)), Palette([empty, RED, plane2, both]));
+        assert_eq!(Theme::Octo.palette().with(Some(RED), None), Palette([RED, plane1, plane2, both]));
+        assert_eq!(Theme::Octo.palette().with(Some(BLACK), Some(WHITE)), Palette::two_color(BLACK, WHITE));
+        assert_eq!(Theme::Octo.palette().with(None, None), Theme::Octo.palette());
+    }
+}
@@ -0,0 +1,194 @@
+use std::fmt;
+use std::str::FromStr;
+
+use crate::chip9::{Display, DISPLAY_HEIGHT, DISPLAY_PLANES, DISPLAY_WIDTH};
+use crate::palette::{Color, Palette};
+
+const CUTOFF: f32 = 1.0 / 32.0; // glow below which a pixel is dark
+
+/// How long pixels keep glowing after they're turned off
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Persistence {
+    /// Pixels go dark at once
+    #[default]
+    Off,
+    /// Fades out over a few frames, enough to hide flicker
+    Light,
+    /// Fades out over about a quarter of a second, like an old CRT
+    Heavy,
+}
+
+impl Persistence {
+    pub const ALL: [Persistence; 3] = [Persistence::Off, Persistence::Light, Persistence::Heavy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Persistence::Off => "off",
+            Persistence::Light => "light",
+            Persistence::Heavy => "heavy",
+        }
+    }
+
+    // Glow kept from one frame to the next
+    fn decay(&self) -> f32 {
+        match self {
+            Persistence::Off => 0.0,
+            Persistence::Light => 0.5,
+            Persistence::Heavy => 0.8,
+        }
+    }
+}
+
+impl fmt::Display for Persistence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Persistence {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Persistence::ALL.into_iter()
+            .find(|persistence| persistence.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<&str> = Persistence::ALL.iter().map(Persistence::name).collect();
+                format!("unknown persistence '{}', expected one of: {}", s, names.join(", "))
+            })
+    }
+}
+
+/// Fades pixels out across successive frames of a display
+pub struct Phosphor {
+    decay: f32,
+    width: usize, // of the last frame
+    height: usize,
+    glow: Vec<f32>, // 1.0 while lit, decaying once turned off
+    pixels: Vec<u8>, // value each pixel had when it was last lit
+}
+
+impl Phosphor {
+    pub fn new(persistence: Persistence) -> Self {
+        Self {
+            decay: persistence.decay(),
+            width: 0,
+            height: 0,
+            glow: vec![0.0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+            pixels: vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT],
+        }
+    }
+
+    /// Takes the next frame of `display`, should be called once per presented frame
+    pub fn update(&mut self, display: &Display) {
+        // Pixels don't carry over a resolution change
+        if (display.width(), display.height()) != (self.width, self.height) {
+            (self.width, self.height) = (display.width(), display.height());
+            self.glow.fill(0.0);
+        }
+
+        for y in 0..self.height {
+            let rows: [u128; DISPLAY_PLANES] = std::array::from_fn(|plane| display.plane(plane)[y]);
+            for x in 0..self.width {
+                let i = x + y * self.width;
+                let bit = u128::BITS as usize - 1 - x;
+                let pixel = rows.iter()
+                    .enumerate()
+                    .fold(0, |pixel, (plane, row)| pixel | ((row >> bit) as u8 & 1) << plane);
+                match pixel {
+                    0 => {
+                        self.glow[i] *= self.decay;
+                        if self.glow[i] < CUTOFF {
+                            self.glow[i] = 0.0;
+                        }
+                    }
+                    pixel => {
+                        self.glow[i] = 1.0;
+                        self.pixels[i] = pixel;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Are any pixels still fading out? The colors change with every update until they're done
+    pub fn is_fading(&self) -> bool {
+        self.glow.iter().any(|&glow| glow > 0.0 && glow < 1.0)
+    }
+
+    /// Color of a pixel of the last frame, in the active resolution
+    pub fn color(&self, x: usize, y: usize, palette: &Palette) -> Color {
+        let i = x + y * self.width;
+        match self.glow[i] {
+            glow if glow >= 1.0 => palette.get(self.pixels[i]),
+            glow if glow > 0.0 => palette.get(0).mix(palette.get(self.pixels[i]), glow),
+            _ => palette.get(0),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip9::{Chip9, Quirks};
+
+    // Lores or hires display, with the top left pixel lit or empty
+    fn display(hires: bool, lit: bool) -> Display {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        let mode = if hires { 0xFF } else { 0xFE };
+        let [high, low] = if lit { [0xD0, 0x01] } else { [0x60, 0x00] }; // draw 1 row to (0, 0), or V0 := 0
+        chip9.load_rom(&[0x00, mode, 0xA2, 0x08, high, low, 0x12, 0x06, 0x80]).unwrap();
+        chip9.run_frame(3).unwrap();
+        chip9.display
+    }
+
+    // Updates with an empty display after the pixel was lit, until it's dark
+    fn fade_out(persistence: Persistence) -> usize {
+        let palette = Palette::default();
+        let mut phosphor = Phosphor::new(persistence);
+        phosphor.update(&display(false, true));
+        assert_eq!(phosphor.color(0, 0, &palette), palette.get(1));
+
+        let dark = display(false, false);
+        let mut updates = 0;
+        loop {
+            phosphor.update(&dark);
+            updates += 1;
+            if phosphor.color(0, 0, &palette) == palette.get(0) {
+                assert!(!phosphor.is_fading());
+                return updates;
+            }
+            assert!(phosphor.is_fading());
+        }
+    }
+
+    #[test]
+    fn pixels_fade_out() {
+        assert_eq!(fade_out(Persistence::Off), 1);
+        assert_eq!(fade_out(Persistence::Light), 6);
+        assert_eq!(fade_out(Persistence::Heavy), 16);
+    }
+
+    #[test]
+    fn fading_pixels_mix_towards_the_background() {
+        let palette = Palette::default();
+        let mut phosphor = Phosphor::new(Persistence::Light);
+        phosphor.update(&display(false, true));
+        phosphor.update(&display(false, false));
+        assert_eq!(phosphor.color(0, 0, &palette), palette.get(0).mix(palette.get(1), 0.5));
+        assert_eq!(phosphor.color(1, 0, &palette), palette.get(0));
+    }
+
+    #[test]
+    fn resolution_change_resets_the_glow() {
+        let palette = Palette::default();
+        let mut phosphor = Phosphor::new(Persistence::Heavy);
+        phosphor.update(&display(false, true));
+        phosphor.update(&display(true, false));
+        assert_eq!(phosphor.color(0, 0, &palette), palette.get(0));
+        assert!(!phosphor.is_fading());
+
+        phosphor.update(&display(true, true));
+        phosphor.update(&display(false, false));
+        assert_eq!(phosphor.color(0, 0, &palette), palette.get(0));
+    }
+}
@@ -0,0 +1,205 @@
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use gif::{Encoder, Repeat};
+
+use crate::chip9::{Display, DISPLAY_HEIGHT, DISPLAY_WIDTH, FRAME_RATE};
+use crate::errors::Chip9Error;
+use crate::palette::Palette;
+
+// Browsers show frames shorter than this, in 1/100s, at 1/10s instead, so shorter ones are skipped
+const MIN_DELAY: u16 = 2;
+
+/// Records the display into an animated GIF, one `capture` per 60Hz frame.
+/// Frames are drawn at the hires resolution, lores pixels take 2x2 hires pixels
+pub struct Recorder {
+    encoder: Encoder<BufWriter<File>>,
+    palette: Palette, // global palette of the file, frames in other colors carry their own
+    scale: usize,
+    frames: u64, // captured so far
+    pending: Option<Capture>, // latest distinct frame, written once it's replaced and its delay is known
+    written: Option<Capture>, // last frame in the file, changes are written relative to it
+}
+
+struct Capture {
+    pixels: Vec<u8>, // hires pixel values row by row
+    palette: Palette,
+    frame: u64, // first frame shown
+}
+
+impl Recorder {
+    pub fn create(path: &Path, palette: &Palette, scale: usize) -> Result<Self, Chip9Error> {
+        let scale = scale.max(1);
+        let size = |pixels: usize| pixels.checked_mul(scale).and_then(|size| u16::try_from(size).ok());
+        let (Some(width), Some(height)) = (size(DISPLAY_WIDTH), size(DISPLAY_HEIGHT)) else {
+            let message = format!("scale {} is too large, GIFs are at most {} pixels wide", scale, u16::MAX);
+            return Err(Chip9Error::RecordingError(io::Error::new(io::ErrorKind::InvalidInput, message).into()));
+        };
+        let file = File::create(path).map_err(|e| Chip9Error::RecordingError(e.into()))?;
+        let mut encoder = Encoder::new(BufWriter::new(file), width, height, &colors(palette))
+            .map_err(Chip9Error::RecordingError)?;
+        encoder.set_repeat(Repeat::Infinite).map_err(Chip9Error::RecordingError)?;
+
+        Ok(Self { encoder, palette: *palette, scale, frames: 0, pending: None, written: None })
+    }
+
+    /// Adds a frame of the display in `palette`, repeats of the previous frame only lengthen it
+    pub fn capture(&mut self, display: &Display, palette: &Palette) -> Result<(), Chip9Error> {
+        let (width, height) = (display.width(), display.height());
+        let pixels: Vec<u8> = (0..DISPLAY_HEIGHT)
+            .flat_map(|y| (0..DISPLAY_WIDTH).map(move |x| (x, y)))
+            .map(|(x, y)| display.pixel(x * width / DISPLAY_WIDTH, y * height / DISPLAY_HEIGHT))
+            .collect();
+        let frame = self.frames;
+        self.frames += 1;
+
+        let capture = match self.pending.take() {
+            Some(pending) if pending.pixels == pixels && pending.palette == *palette => pending,
+            Some(pending) if self.delay(pending.frame, frame) < MIN_DELAY => Capture { pixels, palette: *palette, frame: pending.frame },
+            Some(pending) => {
+                self.write(pending, frame)?;
+                Capture { pixels, palette: *palette, frame }
+            }
+            None => Capture { pixels, palette: *palette, frame },
+        };
+        self.pending = Some(capture);
+        Ok(())
+    }
+
+    /// Writes the last frame and the end of the file
+    pub fn finish(mut self) -> Result<(), Chip9Error> {
+        if let Some(pending) = self.pending.take() {
+            let end = self.frames.max(pending.frame + 1);
+            self.write(pending, end)?;
+        }
+        self.encoder.into_inner()
+            .and_then(|mut output| output.flush())
+            .map_err(|e| Chip9Error::RecordingError(e.into()))
+    }
+
+    // In 1/100s, rounded from the frame boundaries so the delays don't drift from 60Hz
+    fn delay(&self, from: u64, to: u64) -> u16 {
+        let centis = |frame: u64| (frame * 100 + FRAME_RATE as u64 / 2) / FRAME_RATE as u64;
+        (centis(to) - centis(from)).min(u16::MAX as u64) as u16
+    }
+
+    // Writes only the area that changed since the last frame written, or everything when the colors changed
+    fn write(&mut self, capture: Capture, end: u64) -> Result<(), Chip9Error> {
+        let (left, top, right, bottom) = match &self.written {
+            Some(written) if written.palette == capture.palette => changed_area(&written.pixels, &capture.pixels),
+            _ => (0, 0, DISPLAY_WIDTH, DISPLAY_HEIGHT),
+        };
+        let scale = self.scale;
+        let buffer: Vec<u8> = (top * scale..bottom * scale)
+            .flat_map(|y| (left * scale..right * scale).map(move |x| (x, y)))
+            .map(|(x, y)| capture.pixels[x / scale + y / scale * DISPLAY_WIDTH])
+            .collect();
+
+        let frame = gif::Frame {
+            left: (left * scale) as u16,
+            top: (top * scale) as u16,
+            width: ((right - left) * scale) as u16,
+            height: ((bottom - top) * scale) as u16,
+            buffer: buffer.into(),
+            delay: self.delay(capture.frame, end).max(MIN_DELAY),
+            palette: (capture.palette != self.palette).then(|| colors(&capture.palette)),
+            ..gif::Frame::default()
+        };
+        self.encoder.write_frame(&frame).map_err(Chip9Error::RecordingError)?;
+        self.written = Some(capture);
+        Ok(())
+    }
+}
+
+// Bounds of the pixels that differ as left, top, right and bottom, at least one pixel large
+fn changed_area(before: &[u8], after: &[u8]) -> (usize, usize, usize, usize) {
+    let changed = || (0..before.len())
+        .filter(|&i| before[i] != after[i])
+        .map(|i| (i % DISPLAY_WIDTH, i / DISPLAY_WIDTH));
+    let left = changed().map(|(x, _)| x).min().unwrap_or(0);
+    let right = changed().map(|(x, _)| x + 1).max().unwrap_or(1);
+    let top = changed().map(|(_, y)| y).min().unwrap_or(0);
+    let bottom = changed().map(|(_, y)| y + 1).max().unwrap_or(1);
+    (left, top, right, bottom)
+}
+
+// As a GIF color table
+fn colors(palette: &Palette) -> Vec<u8> {
+    palette.0.iter().flat_map(|color| color.rgb()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip9::{Chip9, Quirks};
+
+    // Lores display with the top left pixel lit
+    fn dot() -> Display {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(&[0xA2, 0x06, 0xD0, 0x01, 0x12, 0x04, 0x80]).unwrap(); // draw 1 row at I = 0x206 to (0, 0), loop
+        chip9.run_frame(2).unwrap();
+        chip9.display
+    }
+
+    // Delay and pixels of each frame in the GIF recorded from one capture per display
+    fn record(name: &str, displays: &[&Display]) -> Vec<(u16, Vec<u8>)> {
+        let path = std::env::temp_dir().join(format!("chip9-{}-{}.gif", name, std::process::id()));
+        let mut recorder = Recorder::create(&path, &Palette::default(), 1).unwrap();
+        for display in displays {
+            recorder.capture(display, &Palette::default()).unwrap();
+        }
+        recorder.finish().unwrap();
+
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(File::open(&path).unwrap()).unwrap();
+        let mut frames = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().unwrap() {
+            frames.push((frame.delay, frame.buffer.to_vec()));
+        }
+        std::fs::remove_file(&path).unwrap();
+        frames
+    }
+
+    #[test]
+    fn repeated_frames_are_lengthened() {
+        let (blank, dot) = (Display::new(), dot());
+        let frames = record("repeats", &[&blank, &blank, &blank, &dot, &dot, &dot]);
+
+        // 3 frames at 60Hz are 5/100s, the second frame only covers the changed 2x2 hires pixels
+        assert_eq!(frames, [(5, vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT]), (5, vec![1; 4])]);
+    }
+
+    #[test]
+    fn delays_keep_to_60hz() {
+        let (blank, dot) = (Display::new(), dot());
+        let displays: Vec<&Display> = (0..FRAME_RATE as usize * 10)
+            .map(|frame| if frame / 2 % 2 == 0 { &blank } else { &dot })
+            .collect();
+        let frames = record("drift", &displays);
+
+        // 2 frames are 3.33/100s, each delay is rounded but together they take exactly 10 seconds
+        assert_eq!(frames.len(), displays.len() / 2);
+        assert!(frames.iter().all(|&(delay, _)| delay == 3 || delay == 4));
+        assert_eq!(frames.iter().map(|&(delay, _)| delay as u32).sum::<u32>(), 1000);
+    }
+
+    #[test]
+    fn frames_shorter_than_min_delay_are_merged_into_the_next() {
+        let (blank, dot) = (Display::new(), dot());
+        let frames = record("short", &[&blank, &dot, &blank, &dot]);
+
+        // The dot in the second frame would be shown for 1/100s, it's replaced by the blank frame
+        // that follows it, which takes its time, so the file still lasts 4 frames (7/100s)
+        assert_eq!(frames, [(2, vec![0; DISPLAY_WIDTH * DISPLAY_HEIGHT]), (3, vec![0]), (2, vec![1; 4])]);
+    }
+
+    #[test]
+    fn too_large_scale_is_an_error() {
+        let path = std::env::temp_dir().join(format!("chip9-scale-{}.gif", std::process::id()));
+        let result = Recorder::create(&path, &Palette::default(), u16::MAX as usize / DISPLAY_WIDTH + 1);
+        assert!(matches!(result, Err(Chip9Error::RecordingError(_))));
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::VecDeque;
+
+use crate::Chip9;
+use crate::chip9::FRAME_RATE;
+use crate::errors::Chip9Error;
+
+pub const DEFAULT_REWIND_SECONDS: u32 = 10;
+const CHECKPOINT_INTERVAL: usize = 10; // frames between snapshots
+
+struct Checkpoint {
+    state: Vec<u8>, // full save state for the newest checkpoint, delta against the next newer one otherwise
+    inputs: Vec<u16>, // keys held on each frame run since the snapshot
+    mid_frame: bool, // taken after single instructions, the frames before it ended at the previous checkpoint's last input
+}
+
+/// History of the last few seconds of a running machine. Snapshots are taken
+/// every few frames and the frames in between are re-executed from the keys
+/// recorded for them, so the machine can be stepped back one frame at a time
+pub struct Rewind {
+    checkpoints: VecDeque<Checkpoint>,
+    capacity: usize, // checkpoints kept
+}
+
+impl Rewind {
+    /// Keeps about `seconds` of history, none when 0
+    pub fn new(seconds: u32) -> Self {
+        let frames = (seconds * FRAME_RATE) as usize;
+        Self {
+            checkpoints: VecDeque::new(),
+            capacity: frames.div_ceil(CHECKPOINT_INTERVAL),
+        }
+    }
+
+    /// Forgets the history, e.g. after a save state was loaded
+    pub fn clear(&mut self) {
+        self.checkpoints.clear();
+    }
+
+    /// Records the frame about to run, should be called after the keyboard is updated
+    pub fn record(&mut self, chip9: &Chip9) -> Result<(), Chip9Error> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+
+        if self.checkpoints.back().is_none_or(|newest| newest.inputs.len() == CHECKPOINT_INTERVAL) {
+            self.push(chip9, false)?;
+        }
+
+        self.checkpoints.back_mut().unwrap().inputs.push(chip9.keyboard.mask());
+        Ok(())
+    }
+
+    /// Snapshots the machine in the middle of a frame, e.g. after stepping single instructions,
+    /// so the frames run next are replayed from where they really started
+    pub fn checkpoint(&mut self, chip9: &Chip9) -> Result<(), Chip9Error> {
+        if self.capacity == 0 {
+            return Ok(());
+        }
+
+        // A snapshot no frame was run from yet is replaced
+        if self.checkpoints.back().is_some_and(|newest| newest.inputs.is_empty()) {
+            let newest = self.checkpoints.pop_back().unwrap();
+            if let Some(previous) = self.checkpoints.back_mut() {
+                previous.state = apply_delta(&previous.state, &newest.state);
+            }
+        }
+        self.push(chip9, true)
+    }
+
+    fn push(&mut self, chip9: &Chip9, mid_frame: bool) -> Result<(), Chip9Error> {
+        let mut state = Vec::new();
+        chip9.save_state(&mut state)?;
+        if let Some(newest) = self.checkpoints.back_mut() {
+            newest.state = encode_delta(&newest.state, &state);
+        }
+        self.checkpoints.push_back(Checkpoint { state, inputs: Vec::with_capacity(CHECKPOINT_INTERVAL), mid_frame });
+        if self.checkpoints.len() > self.capacity {
+            self.checkpoints.pop_front();
+        }
+        Ok(())
+    }
+
+    /// Puts the machine back by one frame. Returns false when there's no history left
+    pub fn step_back(&mut self, chip9: &mut Chip9, ipf: u32) -> Result<bool, Chip9Error> {
+        let Some(newest) = self.checkpoints.back() else {
+            return Ok(false);
+        };
+
+        // The machine is at the newest snapshot, continue from the one before it. A snapshot
+        // taken mid-frame goes back to the end of the last whole frame instead of the one before
+        let mut whole_frame = false;
+        if newest.inputs.is_empty() {
+            if self.checkpoints.len() < 2 {
+                return Ok(false);
+            }
+            let newest = self.checkpoints.pop_back().unwrap();
+            let previous = self.checkpoints.back_mut().unwrap();
+            previous.state = apply_delta(&previous.state, &newest.state);
+            whole_frame = newest.mid_frame;
+        }
+
+        let newest = self.checkpoints.back_mut().unwrap();
+        if !whole_frame {
+            newest.inputs.pop();
+        }
+        chip9.load_state(&mut newest.state.as_slice())?;
+        for &keys in &newest.inputs {
+            chip9.keyboard.set_mask(keys);
+            chip9.replay_frame(ipf)?;
+        }
+        Ok(true)
+    }
+}
+
+/*
+    Deltas are a list of runs of changed bytes:
+
+        skip   - u32, unchanged bytes since the end of the previous run
+        length - u32
+        bytes  - `length` bytes of old XOR new
+
+    States of one machine always have the same length.
+*/
+fn encode_delta(old: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut delta = Vec::new();
+    let mut pos = 0;
+    while pos < old.len() {
+        let Some(start) = (pos..old.len()).find(|&i| old[i] != new[i]) else {
+            break;
+        };
+        let end = (start..old.len()).find(|&i| old[i] == new[i]).unwrap_or(old.len());
+
+        delta.extend_from_slice(&((start - pos) as u32).to_le_bytes());
+        delta.extend_from_slice(&((end - start) as u32).to_le_bytes());
+        delta.extend((start..end).map(|i| old[i] ^ new[i]));
+        pos = end;
+    }
+    delta
+}
+
+// Rebuilds the old state from a delta and the new state
+fn apply_delta(delta: &[u8], new: &[u8]) -> Vec<u8> {
+    let mut old = new.to_vec();
+    let read_u32 = |at: usize| u32::from_le_bytes(delta[at..at + 4].try_into().unwrap()) as usize;
+
+    let (mut pos, mut at) = (0, 0);
+    while at < delta.len() {
+        let start = pos + read_u32(at);
+        let length = read_u32(at + 4);
+        at += 8;
+        for (byte, change) in old[start..start + length].iter_mut().zip(&delta[at..at + length]) {
+            *byte ^= change;
+        }
+        at += length;
+        pos = start + length;
+    }
+    old
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{self, Write};
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::chip9::{Quirks, Trace, TraceFilter};
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn step_back_restores_earlier_frames() {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // V0 += 1, repeat
+        let mut rewind = Rewind::new(1);
+        for _ in 0..25 {
+            rewind.record(&chip9).unwrap();
+            chip9.run_frame(2).unwrap();
+        }
+
+        for frame in (20..25).rev() {
+            assert!(rewind.step_back(&mut chip9, 2).unwrap());
+            assert_eq!(chip9.cpu().registers()[0], frame);
+        }
+    }
+
+    #[test]
+    fn step_back_from_a_mid_frame_checkpoint() {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(&[0x70, 0x01, 0x71, 0x01, 0x12, 0x00]).unwrap(); // V0 += 1, V1 += 1, repeat
+        let mut rewind = Rewind::new(1);
+        for _ in 0..3 {
+            rewind.record(&chip9).unwrap();
+            chip9.run_frame(3).unwrap();
+        }
+        chip9.tick().unwrap();
+        rewind.checkpoint(&chip9).unwrap();
+        chip9.tick().unwrap();
+        rewind.checkpoint(&chip9).unwrap();
+        for _ in 0..2 {
+            rewind.record(&chip9).unwrap();
+            chip9.run_frame(3).unwrap();
+        }
+        assert_eq!(chip9.cpu().registers()[..2], [6, 6]);
+
+        // Frames after the steps replay from the stepped position, then the steps are undone
+        let mut history = Vec::new();
+        while rewind.step_back(&mut chip9, 3).unwrap() {
+            history.push(chip9.cpu().registers()[0..2].to_vec());
+        }
+        assert_eq!(history, [vec![5, 5], vec![4, 4], vec![3, 3], vec![2, 2], vec![1, 1], vec![0, 0]]);
+    }
+
+    #[test]
+    fn replayed_frames_are_not_traced() {
+        let output = SharedBuffer::default();
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(&[0x70, 0x01, 0x12, 0x00]).unwrap(); // V0 += 1, repeat
+        chip9.set_trace(Trace::new(Box::new(output.clone()), TraceFilter::default()));
+        let mut rewind = Rewind::new(1);
+        for _ in 0..5 {
+            rewind.record(&chip9).unwrap();
+            chip9.run_frame(2).unwrap();
+        }
+
+        rewind.step_back(&mut chip9, 2).unwrap();
+        rewind.step_back(&mut chip9, 2).unwrap();
+
+        let lines = output.0.lock().unwrap().iter().filter(|&&byte| byte == b'\n').count();
+        assert_eq!(lines, 10);
+    }
+}
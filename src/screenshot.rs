@@ -0,0 +1,144 @@
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::chip9::Display;
+use crate::palette::{Color, Palette};
+
+/// Pixel size of screenshots taken with the hotkey
+pub const DEFAULT_SCREENSHOT_SCALE: usize = 4;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ImageFormat {
+    Ascii,
+    Pbm,
+    Pgm,
+    Png,
+}
+
+impl ImageFormat {
+    /// Picks the format from the file extension, anything unknown is ASCII art
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("pbm") => ImageFormat::Pbm,
+            Some(ext) if ext.eq_ignore_ascii_case("pgm") => ImageFormat::Pgm,
+            Some(ext) if ext.eq_ignore_ascii_case("png") => ImageFormat::Png,
+            _ => ImageFormat::Ascii,
+        }
+    }
+}
+
+/// Writes the display with each pixel drawn as a `scale` by `scale` square, ASCII art is never scaled
+pub fn write(display: &Display, format: ImageFormat, palette: &Palette, scale: usize, output: impl Write) -> io::Result<()> {
+    match format {
+        ImageFormat::Ascii => write_ascii(display, output),
+        ImageFormat::Pbm => write_pbm(display, scale, output),
+        ImageFormat::Pgm => write_pgm(display, palette, scale, output),
+        ImageFormat::Png => write_png(display, palette, scale, output),
+    }
+}
+
+/// One line per row, `#` for lit pixels and `.` for empty ones
+pub fn write_ascii(display: &Display, mut output: impl Write) -> io::Result<()> {
+    for y in 0..display.height() {
+        let line: String = (0..display.width())
+            .map(|x| if display.pixel(x, y) != 0 { '#' } else { '.' })
+            .collect();
+        writeln!(output, "{}", line)?;
+    }
+    Ok(())
+}
+
+/// Plain PBM, lit pixels are black
+pub fn write_pbm(display: &Display, scale: usize, mut output: impl Write) -> io::Result<()> {
+    let (width, height, pixels) = scaled(display, scale);
+    writeln!(output, "P1")?;
+    writeln!(output, "{} {}", width, height)?;
+    for row in pixels.chunks(width) {
+        let row: Vec<&str> = row.iter()
+            .map(|&pixel| if pixel != 0 { "1" } else { "0" })
+            .collect();
+        writeln!(output, "{}", row.join(" "))?;
+    }
+    Ok(())
+}
+
+/// Binary 8-bit PGM, with the brightness of the palette colors
+pub fn write_pgm(display: &Display, palette: &Palette, scale: usize, mut output: impl Write) -> io::Result<()> {
+    let (width, height, pixels) = scaled(display, scale);
+    write!(output, "P5\n{} {}\n255\n", width, height)?;
+    let data: Vec<u8> = pixels.iter().map(|&pixel| luma(palette.get(pixel))).collect();
+    output.write_all(&data)
+}
+
+/// 8-bit RGB PNG in the palette colors
+pub fn write_png(display: &Display, palette: &Palette, scale: usize, output: impl Write) -> io::Result<()> {
+    let (width, height, pixels) = scaled(display, scale);
+    let mut encoder = png::Encoder::new(output, width as u32, height as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+
+    let data: Vec<u8> = pixels.iter().flat_map(|&pixel| palette.get(pixel).rgb()).collect();
+    encoder.write_header()?.write_image_data(&data)?;
+    Ok(())
+}
+
+// Width, height and pixel values row by row, with every pixel repeated `scale` times in both directions
+fn scaled(display: &Display, scale: usize) -> (usize, usize, Vec<u8>) {
+    let scale = scale.max(1);
+    let (width, height) = (display.width() * scale, display.height() * scale);
+    let pixels = (0..height)
+        .flat_map(|y| (0..width).map(move |x| (x, y)))
+        .map(|(x, y)| display.pixel(x / scale, y / scale))
+        .collect();
+    (width, height, pixels)
+}
+
+// ITU-R BT.601 luma
+fn luma(color: Color) -> u8 {
+    let [r, g, b] = color.rgb().map(u32::from);
+    ((r * 299 + g * 587 + b * 114 + 500) / 1000) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip9::{Chip9, Quirks};
+
+    // Lores display with pixels (0, 0), (1, 0) and (1, 1) lit
+    fn display() -> Display {
+        let mut chip9 = Chip9::new(Quirks::CHIP_48);
+        chip9.load_rom(&[0xA2, 0x06, 0xD0, 0x02, 0x12, 0x04, 0xC0, 0x40]).unwrap(); // draw 2 rows at I = 0x206 to (0, 0), loop
+        chip9.run_frame(2).unwrap();
+        chip9.display
+    }
+
+    #[test]
+    fn pbm_at_scale_2() {
+        let mut output = Vec::new();
+        write_pbm(&display(), 2, &mut output).unwrap();
+
+        let mut expected = "P1\n128 64\n".to_string();
+        let empty = " 0".repeat(124);
+        expected += &format!("1 1 1 1{}\n", empty);
+        expected += &format!("1 1 1 1{}\n", empty);
+        expected += &format!("0 0 1 1{}\n", empty);
+        expected += &format!("0 0 1 1{}\n", empty);
+        expected += &format!("0{}{}\n", " 0".repeat(3), empty).repeat(60);
+        assert_eq!(String::from_utf8(output).unwrap(), expected);
+    }
+
+    #[test]
+    fn pgm_at_scale_2() {
+        let palette = Palette::from_colors(&[Color::from((128, 128, 128)), Color::from((0, 0, 255))]).unwrap();
+        let mut output = Vec::new();
+        write_pgm(&display(), &palette, 2, &mut output).unwrap();
+
+        let mut expected = b"P5\n128 64\n255\n".to_vec();
+        for row in [[29, 29, 29, 29], [29, 29, 29, 29], [128, 128, 29, 29], [128, 128, 29, 29]] {
+            expected.extend(row);
+            expected.extend([128; 124]);
+        }
+        expected.extend([128; 128 * 60]);
+        assert_eq!(output, expected);
+    }
+}